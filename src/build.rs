@@ -3,14 +3,22 @@ use std::io;
 use std::path::{Path, PathBuf};
 
 use eyre::{bail, WrapErr};
+use tera::Tera;
 
-use crate::config::Config;
+use crate::config::{CollectionConfig, Config};
 use crate::feed::Feed;
-use crate::template::{EntryTemplateData, FeedTemplateData};
+use crate::redirects::{check_collisions, collect_redirects, write_redirects};
+use crate::robots::generate_robots;
+use crate::template::{
+    find_files, load_templates, CollectionsTemplateData, EntryTemplateData, FeedAuthorTemplateData,
+    FeedTemplateData, TEMPLATE_EXT,
+};
 
 const FEED_TEMPLATE: &str = include_str!("atom.xml.tera");
 
-fn url_to_filepath(base_path: &Path, url_path: &str) -> PathBuf {
+const ROBOTS_FILE_NAME: &str = "robots.txt";
+
+pub fn url_to_filepath(base_path: &Path, url_path: &str) -> PathBuf {
     base_path.join(PathBuf::from_iter(
         url_path.split('/').filter(|segment| !segment.is_empty()),
     ))
@@ -66,57 +74,237 @@ fn copy_dir(src: &Path, dest: &Path) -> eyre::Result<()> {
     Ok(())
 }
 
-pub fn build_capsule(config: &Config) -> eyre::Result<()> {
-    let warn_handler = |msg: &str| eprintln!("Warning: {}", msg);
+// Render each template in the pages directory to the same path in the public directory, sans the
+// `.tera` extension.
+fn render_pages(
+    config: &Config,
+    templates: &mut Tera,
+    feed_data: &FeedTemplateData,
+    collections_data: &CollectionsTemplateData,
+    warn_handler: impl Fn(&str),
+) -> eyre::Result<()> {
+    // The pages directory is optional.
+    if !config.pages_dir.is_dir() {
+        return Ok(());
+    }
 
-    let feed = Feed::from_config(config, warn_handler).wrap_err("failed parsing config file")?;
-    let feed_data = FeedTemplateData::from(feed.clone());
+    let mut page_files = Vec::new();
+    find_files(&config.pages_dir, &config.pages_dir, &mut page_files)
+        .wrap_err("failed reading pages directory")?;
 
-    // Delete the public dir. We do this because static files might have been removed since the
-    // last build, and posts might have been removed or converted to drafts. It's easier to just
-    // start with a new empty directory.
+    for (page_path, relative_path) in page_files {
+        let output_path = match relative_path.strip_suffix(TEMPLATE_EXT) {
+            Some(output_path) => output_path,
+            None => {
+                warn_handler(&format!(
+                    "This file in the pages directory is not a .tera file: {}",
+                    page_path.to_string_lossy()
+                ));
+                continue;
+            }
+        };
 
-    match fs::remove_dir_all(&config.public_dir) {
-        // The public dir not existing is not an error.
-        Err(err) if err.kind() != io::ErrorKind::NotFound => {
-            Err(err).wrap_err("failed removing the public directory")?
+        // Static files are copied over after the pages are rendered, so they replace any page with
+        // the same path.
+        if url_to_filepath(&config.static_dir, output_path).exists() {
+            warn_handler(&format!(
+                "This page is replaced by a file with the same path in your static directory: {}",
+                page_path.to_string_lossy()
+            ));
         }
-        _ => {}
+
+        let output_path = url_to_filepath(&config.public_dir, output_path);
+
+        feed_data
+            .render_page(templates, &page_path, collections_data, &output_path)
+            .wrap_err(format!(
+                "failed rendering page: {}",
+                page_path.to_string_lossy()
+            ))?;
     }
 
-    fs::create_dir_all(&config.public_dir).wrap_err("failed creating the public directory")?;
+    Ok(())
+}
+
+// Generate the index page, Atom feed, and posts for a single collection.
+fn build_collection(
+    config: &Config,
+    collection: &CollectionConfig,
+    feed: &Feed,
+    templates: &Tera,
+    collections_data: &CollectionsTemplateData,
+) -> eyre::Result<()> {
+    let feed_data = FeedTemplateData::from(feed.clone());
 
     // Generate the index page.
 
-    let index_page_path = url_to_filepath(&config.public_dir, &config.index_path);
+    let index_page_path = url_to_filepath(&config.public_dir, &collection.index_path);
     feed_data
-        .render_index(&config.index_template_file, &index_page_path)
+        .render_index(templates, collections_data, &index_page_path)
         .wrap_err("failed rendering index page")?;
 
     // Generate the Atom feed.
 
-    let feed_path = url_to_filepath(&config.public_dir, &config.feed_path);
+    let feed_path = url_to_filepath(&config.public_dir, &collection.feed_path);
     feed_data
         .render_feed(FEED_TEMPLATE, &feed_path)
         .wrap_err("failed rendering Atom feed")?;
 
     // Generate the individual posts.
 
-    for entry in feed.entries {
+    for entry in feed.entries.iter().cloned() {
         let post_path = config.public_dir.join(&entry.path);
 
         EntryTemplateData::from(entry)
-            .render(&feed_data, &config.post_template_file, &post_path)
+            .render(templates, &feed_data, collections_data, &post_path)
             .wrap_err(format!(
                 "failed rendering post: {}",
                 post_path.to_string_lossy()
             ))?;
     }
 
+    Ok(())
+}
+
+pub fn build_capsule(config: &Config) -> eyre::Result<()> {
+    let warn_handler = |msg: &str| eprintln!("Warning: {}", msg);
+
+    let collections = config.all_collections();
+
+    let mut feeds = Vec::new();
+
+    for collection in &collections {
+        feeds.push(
+            Feed::from_config(config, collection, warn_handler).wrap_err(format!(
+                "failed reading posts for collection: {}",
+                collection.name
+            ))?,
+        );
+    }
+
+    let collections_data = feeds
+        .iter()
+        .map(|feed| (feed.name.clone(), FeedTemplateData::from(feed.clone())))
+        .collect::<CollectionsTemplateData>();
+
+    // The first collection is always the main one, configured at the top level of the config.
+    let main_feed = &feeds[0];
+    let main_feed_data = FeedTemplateData::from(main_feed.clone());
+
+    let mut templates = load_templates(config, &collections_data, warn_handler)
+        .wrap_err("failed loading templates")?;
+
+    // Delete the public dir. We do this because static files might have been removed since the
+    // last build, and posts might have been removed or converted to drafts. It's easier to just
+    // start with a new empty directory.
+
+    match fs::remove_dir_all(&config.public_dir) {
+        // The public dir not existing is not an error.
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            Err(err).wrap_err("failed removing the public directory")?
+        }
+        _ => {}
+    }
+
+    fs::create_dir_all(&config.public_dir).wrap_err("failed creating the public directory")?;
+
+    // Generate the index page, Atom feed, and posts for each collection.
+
+    for (collection, feed) in collections.iter().zip(&feeds) {
+        build_collection(config, collection, feed, &templates, &collections_data)
+            .wrap_err(format!("failed building collection: {}", collection.name))?;
+    }
+
+    // Generate the index page and Atom feed for each author.
+
+    for author_feed in main_feed.author_feeds(config)? {
+        let author_data = FeedAuthorTemplateData::from(author_feed.author);
+        let author_feed_data = FeedTemplateData::from(author_feed.feed);
+
+        if let Some(index_path) = &author_feed.index_path {
+            let author_page_path = url_to_filepath(&config.public_dir, index_path);
+            author_feed_data
+                .render_author_index(
+                    &templates,
+                    &author_data,
+                    &collections_data,
+                    &author_page_path,
+                )
+                .wrap_err(format!(
+                    "failed rendering author page: {}",
+                    author_page_path.to_string_lossy()
+                ))?;
+        }
+
+        if let Some(feed_path) = &author_feed.feed_path {
+            let author_feed_path = url_to_filepath(&config.public_dir, feed_path);
+            author_feed_data
+                .render_feed(FEED_TEMPLATE, &author_feed_path)
+                .wrap_err(format!(
+                    "failed rendering author Atom feed: {}",
+                    author_feed_path.to_string_lossy()
+                ))?;
+        }
+    }
+
+    // Generate the index page and Atom feed for each language.
+
+    for lang_feed in main_feed.lang_feeds(config)? {
+        let lang_feed_data = FeedTemplateData::from(lang_feed.feed);
+
+        if let Some(index_path) = &lang_feed.index_path {
+            let lang_page_path = url_to_filepath(&config.public_dir, index_path);
+            lang_feed_data
+                .render_index(&templates, &collections_data, &lang_page_path)
+                .wrap_err(format!(
+                    "failed rendering language index page: {}",
+                    lang_page_path.to_string_lossy()
+                ))?;
+        }
+
+        if let Some(feed_path) = &lang_feed.feed_path {
+            let lang_feed_path = url_to_filepath(&config.public_dir, feed_path);
+            lang_feed_data
+                .render_feed(FEED_TEMPLATE, &lang_feed_path)
+                .wrap_err(format!(
+                    "failed rendering language Atom feed: {}",
+                    lang_feed_path.to_string_lossy()
+                ))?;
+        }
+    }
+
+    // Generate the pages from the pages directory.
+
+    render_pages(
+        config,
+        &mut templates,
+        &main_feed_data,
+        &collections_data,
+        warn_handler,
+    )
+    .wrap_err("failed rendering pages")?;
+
+    // Generate the robots.txt.
+
+    if let Some(robots) = &config.robots {
+        fs::write(
+            config.public_dir.join(ROBOTS_FILE_NAME),
+            generate_robots(robots, &feeds),
+        )
+        .wrap_err("failed writing robots.txt")?;
+    }
+
     // Copy over static content. This clobbers any files generated in previous steps.
 
     copy_dir(&config.static_dir, &config.public_dir)
         .wrap_err("failed copying static content to the public directory")?;
 
+    // Generate the redirect configuration for each Gemini server. This comes last so we can check
+    // for redirects from the paths of files in the public directory.
+
+    let redirects = collect_redirects(config, &feeds, warn_handler)?;
+    check_collisions(config, &redirects, warn_handler);
+    write_redirects(config, &redirects, warn_handler).wrap_err("failed writing redirects")?;
+
     Ok(())
 }
@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use chrono::NaiveDate;
 use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser, Clone)]
@@ -33,6 +34,159 @@ pub struct New {
     #[arg(short, long)]
     pub title: Option<String>,
 
+    /// The name of the collection to create the post in
+    ///
+    /// If this is not specified, the post is created in the posts directory configured at the top
+    /// level of the gempost config file.
+    #[arg(long, value_name = "NAME")]
+    pub collection: Option<String>,
+
+    /// The path of the gempost config file
+    #[arg(short, long, value_name = "PATH", default_value = "./gempost.yaml")]
+    pub config: PathBuf,
+}
+
+#[derive(Args, Clone)]
+pub struct Import {
+    /// The directory containing the posts to import
+    ///
+    /// This is searched recursively for Markdown files with YAML or TOML front matter, like those
+    /// used by Jekyll and Hugo, and gemtext files with date-prefixed filenames, like those used by
+    /// kiln and gssg.
+    pub source_dir: PathBuf,
+
+    /// The name of the collection to import the posts into
+    ///
+    /// If this is not specified, the posts are imported into the posts directory configured at the
+    /// top level of the gempost config file.
+    #[arg(long, value_name = "NAME")]
+    pub collection: Option<String>,
+
+    /// The path of the gempost config file
+    #[arg(short, long, value_name = "PATH", default_value = "./gempost.yaml")]
+    pub config: PathBuf,
+}
+
+#[derive(Args, Clone)]
+pub struct ImportFeed {
+    /// The path of the Atom feed file to import
+    pub feed_file: PathBuf,
+
+    /// The name of the collection to import the posts into
+    ///
+    /// If this is not specified, the posts are imported into the posts directory configured at the
+    /// top level of the gempost config file.
+    #[arg(long, value_name = "NAME")]
+    pub collection: Option<String>,
+
+    /// The path of the gempost config file
+    #[arg(short, long, value_name = "PATH", default_value = "./gempost.yaml")]
+    pub config: PathBuf,
+}
+
+#[derive(Args, Clone)]
+pub struct Export {
+    /// The path of the EPUB file to create
+    #[arg(long, value_name = "PATH")]
+    pub epub: PathBuf,
+
+    /// The name of the collection to export
+    ///
+    /// If this is not specified, the posts configured at the top level of the gempost config file
+    /// are exported.
+    #[arg(long, value_name = "NAME")]
+    pub collection: Option<String>,
+
+    /// The path of the gempost config file
+    #[arg(short, long, value_name = "PATH", default_value = "./gempost.yaml")]
+    pub config: PathBuf,
+}
+
+#[derive(Args, Clone)]
+pub struct List {
+    /// Only list posts in this category
+    #[arg(long, value_name = "NAME")]
+    pub category: Option<String>,
+
+    /// Only list draft posts
+    #[arg(long)]
+    pub drafts: bool,
+
+    /// Only list posts published on or after this date (e.g. 2006-01-02)
+    ///
+    /// Posts without a publish time are filtered by when they were last updated.
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<NaiveDate>,
+
+    /// Print the posts as JSON instead of a table
+    #[arg(long)]
+    pub json: bool,
+
+    /// The name of the collection to list the posts of
+    ///
+    /// If this is not specified, the posts configured at the top level of the gempost config file
+    /// are listed.
+    #[arg(long, value_name = "NAME")]
+    pub collection: Option<String>,
+
+    /// The path of the gempost config file
+    #[arg(short, long, value_name = "PATH", default_value = "./gempost.yaml")]
+    pub config: PathBuf,
+}
+
+#[derive(Args, Clone)]
+pub struct Publish {
+    /// The URL slug of the draft post to publish
+    pub slug: String,
+
+    /// The name of the collection the post is in
+    ///
+    /// If this is not specified, the post is looked for in the posts directory configured at the
+    /// top level of the gempost config file.
+    #[arg(long, value_name = "NAME")]
+    pub collection: Option<String>,
+
+    /// The path of the gempost config file
+    #[arg(short, long, value_name = "PATH", default_value = "./gempost.yaml")]
+    pub config: PathBuf,
+}
+
+#[derive(Args, Clone)]
+pub struct Touch {
+    /// The URL slug of the post to update
+    pub slug: String,
+
+    /// A note about what changed, to add to the post's changelog
+    #[arg(short, long)]
+    pub note: Option<String>,
+
+    /// The name of the collection the post is in
+    ///
+    /// If this is not specified, the post is looked for in the posts directory configured at the
+    /// top level of the gempost config file.
+    #[arg(long, value_name = "NAME")]
+    pub collection: Option<String>,
+
+    /// The path of the gempost config file
+    #[arg(short, long, value_name = "PATH", default_value = "./gempost.yaml")]
+    pub config: PathBuf,
+}
+
+#[derive(Args, Clone)]
+pub struct Rename {
+    /// The current URL slug of the post
+    pub old_slug: String,
+
+    /// The new URL slug of the post
+    pub new_slug: String,
+
+    /// The name of the collection the post is in
+    ///
+    /// If this is not specified, the post is looked for in the posts directory configured at the
+    /// top level of the gempost config file.
+    #[arg(long, value_name = "NAME")]
+    pub collection: Option<String>,
+
     /// The path of the gempost config file
     #[arg(short, long, value_name = "PATH", default_value = "./gempost.yaml")]
     pub config: PathBuf,
@@ -54,4 +208,46 @@ pub enum Commands {
     ///
     /// This generates an empty gemtext file and YAML metadata file, automatically assigning a post ID.
     New(New),
+
+    /// Import posts from another static site generator
+    ///
+    /// This converts each post to gemtext and generates a YAML metadata file for it, skipping posts
+    /// which already exist.
+    Import(Import),
+
+    /// Import posts from an Atom feed
+    ///
+    /// This creates a post for each entry in a local Atom feed file, keeping the original post IDs
+    /// so feed readers don't show them as new posts.
+    ImportFeed(ImportFeed),
+
+    /// Export your gemlog as an ebook
+    ///
+    /// This packages every published post into an EPUB file, oldest first, with a table of
+    /// contents.
+    Export(Export),
+
+    /// List the posts in your gemlog
+    ///
+    /// This prints each post with its status, dates, slug, title, categories, and URL, newest
+    /// first. Drafts and posts scheduled for the future are included.
+    List(List),
+
+    /// Publish a draft post
+    ///
+    /// This marks the post as no longer being a draft and sets its publish and update times to
+    /// now, leaving the rest of its metadata file as-is.
+    Publish(Publish),
+
+    /// Mark a post as updated
+    ///
+    /// This sets the post's update time to now, leaving the rest of its metadata file as-is, so
+    /// feed readers show your changes.
+    Touch(Touch),
+
+    /// Change the URL slug of a post
+    ///
+    /// This renames the post's files, keeping its ID, and updates links to it in your gemtext
+    /// posts and static files. A redirect from its old path is added to the gempost config file.
+    Rename(Rename),
 }
@@ -0,0 +1,93 @@
+use std::env;
+
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use eyre::bail;
+
+use crate::error::Error;
+
+// When this environment variable is set to a Unix timestamp, we use it as the current time so that
+// builds are reproducible.
+//
+// https://reproducible-builds.org/specs/source-date-epoch/
+const SOURCE_DATE_EPOCH_VAR: &str = "SOURCE_DATE_EPOCH";
+
+// Get the current time, or the time in `SOURCE_DATE_EPOCH` if it's set.
+pub fn now() -> eyre::Result<DateTime<FixedOffset>> {
+    let epoch = match env::var(SOURCE_DATE_EPOCH_VAR) {
+        Ok(epoch) => epoch,
+        Err(env::VarError::NotPresent) => return Ok(Local::now().fixed_offset()),
+        Err(env::VarError::NotUnicode(value)) => bail!(Error::InvalidSourceDateEpoch {
+            value: value.to_string_lossy().into_owned(),
+        }),
+    };
+
+    parse_source_date_epoch(&epoch)
+}
+
+// Parse the value of `SOURCE_DATE_EPOCH`, which must be a whole number of seconds since the Unix
+// epoch.
+fn parse_source_date_epoch(epoch: &str) -> eyre::Result<DateTime<FixedOffset>> {
+    match epoch
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+    {
+        Some(time) => Ok(time.fixed_offset()),
+        None => bail!(Error::InvalidSourceDateEpoch {
+            value: epoch.to_owned()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_unix_timestamps() {
+        assert_eq!(
+            parse_source_date_epoch("1704067200").unwrap().to_rfc3339(),
+            "2024-01-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_source_date_epoch(" 0\n").unwrap().to_rfc3339(),
+            "1970-01-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_source_date_epoch("-86400").unwrap().to_rfc3339(),
+            "1969-12-31T00:00:00+00:00"
+        );
+    }
+
+    fn assert_invalid(epoch: &str) {
+        let err = parse_source_date_epoch(epoch).unwrap_err();
+
+        assert!(
+            matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::InvalidSourceDateEpoch { value }) if value == epoch
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_values() {
+        assert_invalid("");
+        assert_invalid("  ");
+    }
+
+    #[test]
+    fn rejects_values_that_are_not_numbers() {
+        assert_invalid("yesterday");
+        assert_invalid("2024-01-01T00:00:00Z");
+        assert_invalid("1704067200.5");
+    }
+
+    #[test]
+    fn rejects_out_of_range_timestamps() {
+        assert_invalid("99999999999999999999");
+        assert_invalid(&i64::MAX.to_string());
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashSet};
 use std::io;
 use std::path::PathBuf;
 use std::{fs::File, path::Path};
@@ -7,14 +8,71 @@ use serde::Deserialize;
 use url::Url;
 
 use crate::error::Error;
+use crate::file_ext::METADATA_FILE_EXT;
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct RawAuthorConfig {
     pub name: String,
     pub email: Option<String>,
     pub uri: Option<String>,
 }
 
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct RawCollectionConfig {
+    name: String,
+    posts_dir: PathBuf,
+    index_template_file: Option<PathBuf>,
+    post_template_file: Option<PathBuf>,
+    post_path: String,
+    index_path: String,
+    feed_path: String,
+    title: String,
+    subtitle: Option<String>,
+    rights: Option<String>,
+    author: Option<RawAuthorConfig>,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct RawRobotsRuleConfig {
+    user_agents: Vec<String>,
+    #[serde(default)]
+    disallow: Vec<String>,
+    #[serde(default)]
+    collections: Vec<String>,
+    #[serde(default)]
+    drafts: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct RawRobotsConfig {
+    #[serde(default)]
+    rules: Vec<RawRobotsRuleConfig>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+struct RawHooksConfig {
+    #[serde(default)]
+    pre_build: Vec<String>,
+    #[serde(default)]
+    post_build: Vec<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+struct RawRedirectServersConfig {
+    #[serde(default)]
+    agate: bool,
+    gmid: Option<PathBuf>,
+    molly_brown: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct RawProcessorConfig {
+    name: String,
+    command: String,
+    #[serde(default)]
+    extensions: Vec<String>,
+}
+
 #[derive(Debug, PartialEq, Eq, Deserialize)]
 struct RawConfig {
     #[serde(default = "defaults::public_dir")]
@@ -23,6 +81,10 @@ struct RawConfig {
     static_dir: PathBuf,
     #[serde(default = "defaults::posts_dir")]
     posts_dir: PathBuf,
+    #[serde(default = "defaults::pages_dir")]
+    pages_dir: PathBuf,
+    #[serde(default = "defaults::templates_dir")]
+    templates_dir: PathBuf,
     #[serde(default = "defaults::index_template_file")]
     index_template_file: PathBuf,
     #[serde(default = "defaults::post_template_file")]
@@ -33,11 +95,38 @@ struct RawConfig {
     index_path: String,
     #[serde(default = "defaults::feed_path")]
     feed_path: String,
+    author_index_path: Option<String>,
+    author_feed_path: Option<String>,
+    #[serde(default = "defaults::author_template_file")]
+    author_template_file: PathBuf,
+    lang_index_path: Option<String>,
+    lang_feed_path: Option<String>,
+    #[serde(default = "defaults::related_posts_limit")]
+    related_posts_limit: usize,
+    #[serde(default = "defaults::summary_length")]
+    summary_length: usize,
+    #[serde(default = "defaults::excerpt_marker")]
+    excerpt_marker: Option<String>,
     title: String,
     url: String,
     subtitle: Option<String>,
     rights: Option<String>,
     author: Option<RawAuthorConfig>,
+    #[serde(default)]
+    authors: BTreeMap<String, RawAuthorConfig>,
+    #[serde(default)]
+    extra: BTreeMap<String, serde_yaml::Value>,
+    #[serde(default)]
+    collections: Vec<RawCollectionConfig>,
+    robots: Option<RawRobotsConfig>,
+    #[serde(default)]
+    hooks: RawHooksConfig,
+    #[serde(default)]
+    processors: Vec<RawProcessorConfig>,
+    #[serde(default)]
+    redirects: BTreeMap<String, String>,
+    #[serde(default)]
+    redirect_servers: RawRedirectServersConfig,
 }
 
 mod defaults {
@@ -55,6 +144,14 @@ mod defaults {
         PathBuf::from("./posts/")
     }
 
+    pub fn pages_dir() -> PathBuf {
+        PathBuf::from("./pages/")
+    }
+
+    pub fn templates_dir() -> PathBuf {
+        PathBuf::from("./templates/")
+    }
+
     pub fn index_template_file() -> PathBuf {
         PathBuf::from("./templates/index.tera")
     }
@@ -63,6 +160,10 @@ mod defaults {
         PathBuf::from("./templates/post.tera")
     }
 
+    pub fn author_template_file() -> PathBuf {
+        PathBuf::from("./templates/author.tera")
+    }
+
     pub fn post_path() -> String {
         String::from("/posts/{{ slug }}.gmi")
     }
@@ -74,6 +175,18 @@ mod defaults {
     pub fn feed_path() -> String {
         String::from("/posts/atom.xml")
     }
+
+    pub fn related_posts_limit() -> usize {
+        5
+    }
+
+    pub fn summary_length() -> usize {
+        200
+    }
+
+    pub fn excerpt_marker() -> Option<String> {
+        Some(String::from("---more---"))
+    }
 }
 
 impl RawConfig {
@@ -115,41 +228,368 @@ impl From<RawAuthorConfig> for AuthorConfig {
     }
 }
 
+// The name of the collection configured at the top level of the config file.
+pub const MAIN_COLLECTION_NAME: &str = "main";
+
+// A single gemlog within the capsule, with its own posts, index page, and Atom feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionConfig {
+    pub name: String,
+    pub posts_dir: PathBuf,
+    pub index_template_file: PathBuf,
+    pub post_template_file: PathBuf,
+    pub post_path: String,
+    pub index_path: String,
+    pub feed_path: String,
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub rights: Option<String>,
+    pub author: Option<AuthorConfig>,
+}
+
+// A group of rules in the robots.txt for a set of virtual user agents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RobotsRuleConfig {
+    pub user_agents: Vec<String>,
+    pub disallow: Vec<String>,
+    pub collections: Vec<String>,
+    pub drafts: bool,
+}
+
+impl From<RawRobotsRuleConfig> for RobotsRuleConfig {
+    fn from(raw: RawRobotsRuleConfig) -> Self {
+        Self {
+            user_agents: raw.user_agents,
+            disallow: raw.disallow,
+            collections: raw.collections,
+            drafts: raw.drafts,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RobotsConfig {
+    pub rules: Vec<RobotsRuleConfig>,
+}
+
+// Shell commands to run before and after building the capsule.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HooksConfig {
+    pub pre_build: Vec<String>,
+    pub post_build: Vec<String>,
+}
+
+impl From<RawHooksConfig> for HooksConfig {
+    fn from(raw: RawHooksConfig) -> Self {
+        Self {
+            pre_build: raw.pre_build,
+            post_build: raw.post_build,
+        }
+    }
+}
+
+// The Gemini servers to generate redirect configuration for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RedirectServersConfig {
+    // Whether to write Agate `.meta` files into the public directory.
+    pub agate: bool,
+    pub gmid: Option<PathBuf>,
+    pub molly_brown: Option<PathBuf>,
+}
+
+impl From<RawRedirectServersConfig> for RedirectServersConfig {
+    fn from(raw: RawRedirectServersConfig) -> Self {
+        Self {
+            agate: raw.agate,
+            gmid: raw.gmid,
+            molly_brown: raw.molly_brown,
+        }
+    }
+}
+
+// A shell command which post bodies are piped through before they're rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessorConfig {
+    pub name: String,
+    pub command: String,
+    pub extensions: Vec<String>,
+}
+
+impl From<RawProcessorConfig> for ProcessorConfig {
+    fn from(raw: RawProcessorConfig) -> Self {
+        Self {
+            name: raw.name,
+            command: raw.command,
+            // Accept extensions written with or without the leading dot.
+            extensions: raw
+                .extensions
+                .into_iter()
+                .map(|ext| ext.trim_start_matches('.').to_owned())
+                .collect(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Config {
     pub public_dir: PathBuf,
     pub static_dir: PathBuf,
     pub posts_dir: PathBuf,
+    pub pages_dir: PathBuf,
+    pub templates_dir: PathBuf,
     pub index_template_file: PathBuf,
     pub post_template_file: PathBuf,
     pub post_path: String,
     pub index_path: String,
     pub feed_path: String,
+    pub author_index_path: Option<String>,
+    pub author_feed_path: Option<String>,
+    pub author_template_file: PathBuf,
+    pub lang_index_path: Option<String>,
+    pub lang_feed_path: Option<String>,
+    pub related_posts_limit: usize,
+    pub summary_length: usize,
+    pub excerpt_marker: Option<String>,
     pub title: String,
     pub url: Url,
     pub subtitle: Option<String>,
     pub rights: Option<String>,
     pub author: Option<AuthorConfig>,
+    pub authors: BTreeMap<String, AuthorConfig>,
+    pub extra: BTreeMap<String, serde_yaml::Value>,
+    pub collections: Vec<CollectionConfig>,
+    pub robots: Option<RobotsConfig>,
+    pub hooks: HooksConfig,
+    pub processors: Vec<ProcessorConfig>,
+    pub redirects: BTreeMap<String, String>,
+    pub redirect_servers: RedirectServersConfig,
 }
 
 impl Config {
     pub fn read(path: &Path) -> eyre::Result<Self> {
         let raw = RawConfig::read(path).wrap_err("failed reading config file")?;
 
+        let mut collection_names = HashSet::from([MAIN_COLLECTION_NAME.to_owned()]);
+
+        for collection in &raw.collections {
+            if !collection_names.insert(collection.name.clone()) {
+                bail!(Error::InvalidConfigFile {
+                    path: path.to_owned(),
+                    reason: format!(
+                        "There is more than one collection named `{}`. Collection names must be unique, and `{MAIN_COLLECTION_NAME}` is reserved for the posts configured at the top level.",
+                        collection.name
+                    ),
+                });
+            }
+        }
+
+        if let Some(robots) = &raw.robots {
+            for rule in &robots.rules {
+                if let Some(name) = rule
+                    .collections
+                    .iter()
+                    .find(|name| !collection_names.contains(*name))
+                {
+                    bail!(Error::InvalidConfigFile {
+                        path: path.to_owned(),
+                        reason: format!(
+                            "A robots.txt rule refers to a collection named `{name}`, but there is no collection with that name."
+                        ),
+                    });
+                }
+            }
+        }
+
+        let mut processor_names = HashSet::new();
+
+        for processor in &raw.processors {
+            if !processor_names.insert(processor.name.clone()) {
+                bail!(Error::InvalidConfigFile {
+                    path: path.to_owned(),
+                    reason: format!(
+                        "There is more than one processor named `{}`. Processor names must be unique.",
+                        processor.name
+                    ),
+                });
+            }
+
+            if processor
+                .extensions
+                .iter()
+                .any(|ext| ext.trim_start_matches('.') == METADATA_FILE_EXT)
+            {
+                bail!(Error::InvalidConfigFile {
+                    path: path.to_owned(),
+                    reason: format!(
+                        "The processor `{}` can't be used for .{METADATA_FILE_EXT} files, because those are post metadata files.",
+                        processor.name
+                    ),
+                });
+            }
+        }
+
+        // A blank marker would match every blank line.
+        if raw
+            .excerpt_marker
+            .as_ref()
+            .is_some_and(|marker| marker.trim().is_empty())
+        {
+            bail!(Error::InvalidConfigFile {
+                path: path.to_owned(),
+                reason: String::from(
+                    "The `excerpt_marker` can't be blank. To turn off excerpts, set it to `null`."
+                ),
+            });
+        }
+
+        for (source, target) in &raw.redirects {
+            if !source.starts_with('/') {
+                bail!(Error::InvalidConfigFile {
+                    path: path.to_owned(),
+                    reason: format!(
+                        "The redirect from `{source}` must be from a path on your capsule, starting with a `/`."
+                    ),
+                });
+            }
+
+            if !target.starts_with('/') && Url::parse(target).is_err() {
+                bail!(Error::InvalidConfigFile {
+                    path: path.to_owned(),
+                    reason: format!(
+                        "The redirect from `{source}` must be to either a path on your capsule, starting with a `/`, or a full URL."
+                    ),
+                });
+            }
+        }
+
+        // Collections fall back to the top-level templates, rights, and author.
+        let collections = raw
+            .collections
+            .into_iter()
+            .map(|collection| CollectionConfig {
+                name: collection.name,
+                posts_dir: collection.posts_dir,
+                index_template_file: collection
+                    .index_template_file
+                    .unwrap_or_else(|| raw.index_template_file.clone()),
+                post_template_file: collection
+                    .post_template_file
+                    .unwrap_or_else(|| raw.post_template_file.clone()),
+                post_path: collection.post_path,
+                index_path: collection.index_path,
+                feed_path: collection.feed_path,
+                title: collection.title,
+                subtitle: collection.subtitle,
+                rights: collection.rights.or_else(|| raw.rights.clone()),
+                author: collection
+                    .author
+                    .map(Into::into)
+                    .or_else(|| raw.author.clone().map(Into::into)),
+            })
+            .collect();
+
         Ok(Self {
             public_dir: raw.public_dir,
             static_dir: raw.static_dir,
             posts_dir: raw.posts_dir,
+            pages_dir: raw.pages_dir,
+            templates_dir: raw.templates_dir,
             index_template_file: raw.index_template_file,
             post_template_file: raw.post_template_file,
             post_path: raw.post_path,
             index_path: raw.index_path,
             feed_path: raw.feed_path,
+            author_index_path: raw.author_index_path,
+            author_feed_path: raw.author_feed_path,
+            author_template_file: raw.author_template_file,
+            lang_index_path: raw.lang_index_path,
+            lang_feed_path: raw.lang_feed_path,
+            related_posts_limit: raw.related_posts_limit,
+            summary_length: raw.summary_length,
+            excerpt_marker: raw.excerpt_marker,
             title: raw.title,
             url: Url::parse(&raw.url).map_err(|_| Error::InvalidCapsuleUrl { url: raw.url })?,
             subtitle: raw.subtitle,
             rights: raw.rights,
             author: raw.author.map(Into::into),
+            authors: raw
+                .authors
+                .into_iter()
+                .map(|(handle, author)| (handle, author.into()))
+                .collect(),
+            extra: raw.extra,
+            collections,
+            robots: raw.robots.map(|robots| RobotsConfig {
+                rules: robots.rules.into_iter().map(Into::into).collect(),
+            }),
+            hooks: raw.hooks.into(),
+            processors: raw.processors.into_iter().map(Into::into).collect(),
+            redirects: raw.redirects,
+            redirect_servers: raw.redirect_servers.into(),
         })
     }
+
+    // The collection configured at the top level of the config file.
+    pub fn main_collection(&self) -> CollectionConfig {
+        CollectionConfig {
+            name: MAIN_COLLECTION_NAME.to_owned(),
+            posts_dir: self.posts_dir.clone(),
+            index_template_file: self.index_template_file.clone(),
+            post_template_file: self.post_template_file.clone(),
+            post_path: self.post_path.clone(),
+            index_path: self.index_path.clone(),
+            feed_path: self.feed_path.clone(),
+            title: self.title.clone(),
+            subtitle: self.subtitle.clone(),
+            rights: self.rights.clone(),
+            author: self.author.clone(),
+        }
+    }
+
+    // All the collections in the capsule, starting with the main collection.
+    pub fn all_collections(&self) -> Vec<CollectionConfig> {
+        let mut collections = vec![self.main_collection()];
+        collections.extend(self.collections.iter().cloned());
+        collections
+    }
+
+    pub fn collection(&self, name: &str) -> Option<CollectionConfig> {
+        self.all_collections()
+            .into_iter()
+            .find(|collection| collection.name == name)
+    }
+
+    // The collection a command should operate on, which is the main collection unless one is named.
+    pub fn collection_or_main(&self, name: Option<&str>) -> Result<CollectionConfig, Error> {
+        match name {
+            Some(name) => self
+                .collection(name)
+                .ok_or_else(|| Error::NonexistentCollection {
+                    name: name.to_owned(),
+                }),
+            None => Ok(self.main_collection()),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    // A new, empty directory for a test to write files to.
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gempost-test-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Write a config file to the given directory and read it back.
+    pub(crate) fn read_config(dir: &Path, yaml: &str) -> eyre::Result<Config> {
+        let path = dir.join("gempost.yaml");
+        fs::write(&path, yaml).unwrap();
+        Config::read(&path)
+    }
 }
@@ -1,37 +1,97 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::Path;
 use std::{fs::File, path::PathBuf};
 
 use chrono::{DateTime, FixedOffset};
 use eyre::{bail, eyre, WrapErr};
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use url::Url;
 
+use crate::config::{AuthorConfig, Config};
 use crate::error::Error;
+use crate::file_ext::{MARKDOWN_FILE_EXT, METADATA_FILE_EXT, POST_FILE_EXT};
+use crate::markdown;
+use crate::processor::run_processor;
 
-const POST_FILE_EXT: &str = "gmi";
-const METADATA_FILE_EXT: &str = "yaml";
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RawAuthorMetadata {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+}
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
-struct RawAuthorMetadata {
-    name: String,
-    email: Option<String>,
-    uri: Option<String>,
+// An author can either be written out inline or refer to an author in the config file by their
+// handle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum RawAuthorRef {
+    Handle(String),
+    Inline(RawAuthorMetadata),
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
-struct RawEntryMetadata {
-    id: String,
-    title: String,
-    updated: String,
-    summary: Option<String>,
-    published: Option<String>,
-    author: Option<RawAuthorMetadata>,
-    rights: Option<String>,
-    lang: Option<String>,
-    categories: Option<Vec<String>>,
-    draft: Option<bool>,
+// Deriving this with `#[serde(untagged)]` would report every malformed author as not matching any
+// variant, so we check which form the author is written in first.
+impl<'de> Deserialize<'de> for RawAuthorRef {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match serde_yaml::Value::deserialize(deserializer)? {
+            serde_yaml::Value::String(handle) => Ok(Self::Handle(handle)),
+            value @ serde_yaml::Value::Mapping(_) => serde_yaml::from_value(value)
+                .map(Self::Inline)
+                .map_err(|err| de::Error::custom(format!("invalid author: {err}"))),
+            _ => Err(de::Error::custom(
+                "an author must be either the handle of an author in your gempost.yaml or a map with a `name`",
+            )),
+        }
+    }
+}
+
+// A note about a change made to a post after it was published.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RawChangelogEntry {
+    pub updated: String,
+    pub note: String,
+}
+
+// This is also written out when importing posts, so optional values that aren't set are left out.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RawEntryMetadata {
+    pub id: String,
+    pub title: String,
+    pub updated: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<RawAuthorRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contributors: Option<Vec<RawAuthorRef>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rights: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub categories: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub draft: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noindex: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translation_of: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processors: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changelog: Option<Vec<RawChangelogEntry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
 }
 
 // This example comes from the Go standard library.
@@ -58,6 +118,8 @@ pub struct AuthorMetadata {
     pub name: String,
     pub email: Option<String>,
     pub uri: Option<String>,
+    // The handle of the author in the config file, if the post refers to them by their handle.
+    pub handle: Option<String>,
 }
 
 impl From<RawAuthorMetadata> for AuthorMetadata {
@@ -66,10 +128,43 @@ impl From<RawAuthorMetadata> for AuthorMetadata {
             name: raw.name,
             email: raw.email,
             uri: raw.uri,
+            handle: None,
+        }
+    }
+}
+
+impl RawAuthorRef {
+    fn resolve(
+        self,
+        authors: &BTreeMap<String, AuthorConfig>,
+        path: &Path,
+    ) -> Result<AuthorMetadata, Error> {
+        match self {
+            Self::Inline(raw) => Ok(raw.into()),
+            Self::Handle(handle) => match authors.get(&handle) {
+                Some(author) => Ok(AuthorMetadata {
+                    name: author.name.clone(),
+                    email: author.email.clone(),
+                    uri: author.uri.clone(),
+                    handle: Some(handle),
+                }),
+                None => Err(Error::InvalidMetadataFile {
+                    path: path.to_owned(),
+                    reason: format!(
+                        "There is no author with the handle `{handle}` in the `authors` section of your gempost.yaml."
+                    ),
+                }),
+            },
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangelogEntry {
+    pub updated: DateTime<FixedOffset>,
+    pub note: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EntryMetadata {
     pub id: String,
@@ -78,19 +173,40 @@ pub struct EntryMetadata {
     pub summary: Option<String>,
     pub published: Option<DateTime<FixedOffset>>,
     pub author: Option<AuthorMetadata>,
+    pub contributors: Vec<AuthorMetadata>,
     pub rights: Option<String>,
     pub lang: Option<String>,
     pub categories: Vec<String>,
     pub draft: bool,
+    pub noindex: bool,
+    pub translation_of: Option<String>,
+    pub processors: Vec<String>,
+    pub changelog: Vec<ChangelogEntry>,
+    pub aliases: Vec<String>,
+    pub extra: BTreeMap<String, serde_yaml::Value>,
 }
 
 impl EntryMetadata {
-    pub fn read(path: &Path) -> eyre::Result<Self> {
+    pub fn read(path: &Path, authors: &BTreeMap<String, AuthorConfig>) -> eyre::Result<Self> {
         let raw = RawEntryMetadata::read(path).wrap_err(format!(
             "failed reading metadata file: {}",
             path.to_string_lossy()
         ))?;
 
+        if let Some(alias) = raw
+            .aliases
+            .iter()
+            .flatten()
+            .find(|alias| !alias.starts_with('/'))
+        {
+            bail!(Error::InvalidMetadataFile {
+                path: path.to_owned(),
+                reason: format!(
+                    "The alias `{alias}` must be a path on your capsule, starting with a `/`."
+                ),
+            });
+        }
+
         Ok(Self {
             id: raw.id,
             title: raw.title,
@@ -115,22 +231,83 @@ impl EntryMetadata {
                     }
                 }))
                 .transpose()?,
-            author: raw.author.map(Into::into),
+            author: raw
+                .author
+                .map(|author| author.resolve(authors, path))
+                .transpose()?,
+            contributors: raw
+                .contributors
+                .unwrap_or_default()
+                .into_iter()
+                .map(|contributor| contributor.resolve(authors, path))
+                .collect::<Result<_, _>>()?,
             rights: raw.rights,
             lang: raw.lang,
             categories: raw.categories.unwrap_or_default(),
             // If the `draft` property is missing, we assume it's not a draft.
             draft: raw.draft.unwrap_or(false),
+            noindex: raw.noindex.unwrap_or(false),
+            translation_of: raw.translation_of,
+            processors: raw.processors.unwrap_or_default(),
+            changelog: raw
+                .changelog
+                .unwrap_or_default()
+                .into_iter()
+                .map(|change| {
+                    Ok(ChangelogEntry {
+                        updated: DateTime::parse_from_rfc3339(&change.updated).map_err(|_| {
+                            Error::InvalidMetadataFile {
+                                path: path.to_owned(),
+                                reason: format!(
+                                    "The `updated` time of each changelog entry must be in RFC 3339 format (e.g. {EXAMPLE_RFC3339})."
+                                ),
+                            }
+                        })?,
+                        note: change.note,
+                    })
+                })
+                .collect::<Result<_, Error>>()?,
+            aliases: raw.aliases.unwrap_or_default(),
+            extra: raw.extra,
         })
     }
 }
 
+// A reference to another entry, used for navigating between posts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryLink {
+    pub title: String,
+    pub url: Url,
+    pub date: DateTime<FixedOffset>,
+}
+
+// The entries chronologically before and after an entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryNav {
+    pub previous: Option<EntryLink>,
+    pub next: Option<EntryLink>,
+}
+
+// A version of an entry in another language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryTranslation {
+    pub lang: Option<String>,
+    pub title: String,
+    pub url: Url,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Entry {
+    pub slug: String,
     pub metadata: EntryMetadata,
     pub body: String,
+    pub excerpt: Option<String>,
     pub url: Url,
     pub path: PathBuf,
+    pub nav: EntryNav,
+    pub category_nav: BTreeMap<String, EntryNav>,
+    pub related: Vec<EntryLink>,
+    pub translations: Vec<EntryTranslation>,
 }
 
 pub struct PostLocation {
@@ -143,6 +320,12 @@ pub struct PostLocationParams<'a> {
     pub slug: &'a str,
 }
 
+// Whether a slug can be used as the filename of a post in the posts directory. Slugs can't be
+// empty, contain path separators, or start with a `.`, which would make the post a hidden file.
+pub fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty() && !slug.contains(['/', '\\']) && !slug.starts_with('.')
+}
+
 // This returns `None` when either:
 // - There is no filename.
 // - The path is empty or the root path.
@@ -153,27 +336,37 @@ fn change_file_ext(path: &Path, new_ext: &str) -> Option<PathBuf> {
 }
 
 struct PostPathPair {
-    gemtext: PathBuf,
+    post: PathBuf,
     metadata: PathBuf,
 }
 
 // Remove paths from each set that do not have an accompanying path in the other set. Emit warnings
 // when this happens.
 fn check_mismatched_post_files(
-    post_paths: HashSet<PathBuf>,
-    metadata_paths: &HashSet<PathBuf>,
+    post_paths: BTreeSet<PathBuf>,
+    metadata_paths: &BTreeSet<PathBuf>,
     warn_handler: impl Fn(&str),
 ) -> eyre::Result<Vec<PostPathPair>> {
-    // Warn about metadata files that don't have an accompanying gemtext file.
-    for metadata_path in metadata_paths.iter() {
-        let maybe_post_path = match change_file_ext(metadata_path, POST_FILE_EXT) {
-            Some(path) => path,
-            None => bail!("This file has no filename, even though we've already checked for one. This is a bug."),
-        };
+    // Post files can have different extensions, so we match them up with their metadata files by
+    // their path without the extension.
+    let mut post_stems = BTreeMap::new();
+
+    for post_path in post_paths.iter() {
+        let stem = post_path.with_extension("");
+
+        if let Some(other_path) = post_stems.insert(stem, post_path) {
+            bail!(Error::DuplicatePostFiles {
+                first: other_path.to_owned(),
+                second: post_path.to_owned(),
+            });
+        }
+    }
 
-        if !post_paths.contains(&maybe_post_path) {
+    // Warn about metadata files that don't have an accompanying post file.
+    for metadata_path in metadata_paths.iter() {
+        if !post_stems.contains_key(&metadata_path.with_extension("")) {
             warn_handler(&format!(
-                "This YAML metadata file does not have an accompanying gemtext file: {}",
+                "This YAML metadata file does not have an accompanying post file: {}",
                 metadata_path.to_string_lossy()
             ));
         }
@@ -181,7 +374,7 @@ fn check_mismatched_post_files(
 
     let mut pairs = Vec::new();
 
-    // Filter out gemtext files that don't have an accompanying metadata file.
+    // Filter out post files that don't have an accompanying metadata file.
     for post_path in post_paths.into_iter() {
         let maybe_metadata_path = match change_file_ext(&post_path, METADATA_FILE_EXT) {
             Some(path) => path,
@@ -190,12 +383,12 @@ fn check_mismatched_post_files(
 
         if metadata_paths.contains(&maybe_metadata_path) {
             pairs.push(PostPathPair {
-                gemtext: post_path,
+                post: post_path,
                 metadata: maybe_metadata_path,
             });
         } else {
             warn_handler(&format!(
-                "This gemtext file does not have an accompanying YAML metadata file: {}",
+                "This post file does not have an accompanying YAML metadata file: {}",
                 post_path.to_string_lossy()
             ));
         }
@@ -204,9 +397,68 @@ fn check_mismatched_post_files(
     Ok(pairs)
 }
 
+// Run a post body through the processors for its file extension, followed by the processors listed
+// in its metadata file. Markdown posts are converted to gemtext first, unless there are processors
+// configured for Markdown files, in which case those are used instead.
+fn process_post_body(
+    mut body: String,
+    post_path: &Path,
+    metadata: &EntryMetadata,
+    metadata_path: &Path,
+    config: &Config,
+) -> eyre::Result<String> {
+    let post_ext = post_path
+        .extension()
+        .map(|ext| ext.to_string_lossy())
+        .unwrap_or_default();
+
+    let mut processors = config
+        .processors
+        .iter()
+        .filter(|processor| processor.extensions.iter().any(|ext| *ext == post_ext))
+        .collect::<Vec<_>>();
+
+    if processors.is_empty() && post_ext == MARKDOWN_FILE_EXT {
+        body = markdown::to_gemtext(&body);
+    }
+
+    for name in &metadata.processors {
+        match config.processors.iter().find(|processor| processor.name == *name) {
+            Some(processor) => processors.push(processor),
+            None => bail!(Error::InvalidMetadataFile {
+                path: metadata_path.to_owned(),
+                reason: format!(
+                    "There is no processor named `{name}` in the `processors` section of your gempost.yaml."
+                ),
+            }),
+        }
+    }
+
+    for processor in processors {
+        body = run_processor(processor, body, post_path)?;
+    }
+
+    Ok(body)
+}
+
 impl Entry {
+    // The date used to order entries, which is the publish time or, if there is no publish time,
+    // the last updated time.
+    pub fn date(&self) -> DateTime<FixedOffset> {
+        self.metadata.published.unwrap_or(self.metadata.updated)
+    }
+
+    pub fn link(&self) -> EntryLink {
+        EntryLink {
+            title: self.metadata.title.clone(),
+            url: self.url.clone(),
+            date: self.date(),
+        }
+    }
+
     fn from_post_paths(
         path_pairs: &Vec<PostPathPair>,
+        config: &Config,
         locator: impl Fn(PostLocationParams) -> eyre::Result<PostLocation>,
     ) -> eyre::Result<Vec<Self>> {
         let mut entries = Vec::new();
@@ -214,27 +466,34 @@ impl Entry {
         // By this point, we've already removed post paths from the set that do not have an
         // accompanying metadata file.
         for PostPathPair {
-            gemtext: gemtext_path,
+            post: post_path,
             metadata: metadata_path,
         } in path_pairs
         {
-            let post_body = String::from_utf8(
-                fs::read(gemtext_path).wrap_err("failed reading gemtext post body")?,
-            )
-            .wrap_err("gemtext post body is not valid UTF-8")?;
-
-            let post_metadata = EntryMetadata::read(metadata_path)?;
-
-            // We do not publish draft posts.
-            if post_metadata.draft {
-                continue;
-            }
+            let post_metadata = EntryMetadata::read(metadata_path, &config.authors)?;
+
+            // Drafts aren't published, so we only need to know where they will be. Skipping their
+            // body means a draft that doesn't build yet doesn't stop the rest of the capsule from
+            // building.
+            let post_body = if post_metadata.draft {
+                String::new()
+            } else {
+                let raw_body =
+                    String::from_utf8(fs::read(post_path).wrap_err("failed reading post body")?)
+                        .wrap_err("post body is not valid UTF-8")?;
+
+                process_post_body(raw_body, post_path, &post_metadata, metadata_path, config)
+                    .wrap_err(format!(
+                        "failed processing post body: {}",
+                        post_path.to_string_lossy()
+                    ))?
+            };
 
-            let post_slug = gemtext_path
+            let post_slug = post_path
                 .file_stem()
                 .ok_or(eyre!(
                     "This filename does not have a file stem. This is a bug.\n{}",
-                    gemtext_path.to_string_lossy()
+                    post_path.to_string_lossy()
                 ))?
                 .to_string_lossy();
 
@@ -244,10 +503,18 @@ impl Entry {
             })?;
 
             entries.push(Entry {
+                slug: post_slug.into_owned(),
                 metadata: post_metadata,
                 body: post_body,
+                // This is populated once we know the excerpt marker.
+                excerpt: None,
                 url: post_location.url,
                 path: post_location.path,
+                // These are populated once all the entries have been sorted.
+                nav: EntryNav::default(),
+                category_nav: BTreeMap::new(),
+                related: Vec::new(),
+                translations: Vec::new(),
             });
         }
 
@@ -256,17 +523,28 @@ impl Entry {
 
     pub fn from_posts(
         posts_dir: &Path,
+        config: &Config,
         locator: impl Fn(PostLocationParams) -> eyre::Result<PostLocation>,
         warn_handler: impl Fn(&str),
     ) -> eyre::Result<Vec<Self>> {
         let file_entries = fs::read_dir(posts_dir).wrap_err("failed reading posts directory")?;
 
-        let mut post_paths = HashSet::new();
-        let mut metadata_paths = HashSet::new();
+        // We use ordered sets so that the order of posts and warnings doesn't depend on the order
+        // we read them from the filesystem.
+        let mut post_paths = BTreeSet::new();
+        let mut metadata_paths = BTreeSet::new();
+
+        // Processors can make other kinds of files into posts.
+        let post_exts = config
+            .processors
+            .iter()
+            .flat_map(|processor| processor.extensions.iter().map(String::as_str))
+            .chain([POST_FILE_EXT, MARKDOWN_FILE_EXT])
+            .collect::<BTreeSet<_>>();
 
         let warn_unexpected_file_ext = |path: &Path| {
             warn_handler(&format!(
-                "This is not a .gmi or .yaml file: {}",
+                "This is not a .gmi, .md, or .yaml file: {}",
                 path.as_os_str().to_string_lossy()
             ));
         };
@@ -285,8 +563,8 @@ impl Entry {
             };
 
             match path_ext.to_string_lossy().as_ref() {
-                POST_FILE_EXT => post_paths.insert(entry_path),
                 METADATA_FILE_EXT => metadata_paths.insert(entry_path),
+                ext if post_exts.contains(ext) => post_paths.insert(entry_path),
                 _ => {
                     warn_unexpected_file_ext(&entry_path);
                     continue;
@@ -297,6 +575,46 @@ impl Entry {
         let path_pairs = check_mismatched_post_files(post_paths, &metadata_paths, warn_handler)
             .wrap_err("failed checking for mismatched post files")?;
 
-        Self::from_post_paths(&path_pairs, locator)
+        Self::from_post_paths(&path_pairs, config, locator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_author_handles_and_inline_authors() {
+        assert_eq!(
+            serde_yaml::from_str::<RawAuthorRef>("jane").unwrap(),
+            RawAuthorRef::Handle(String::from("jane"))
+        );
+        assert_eq!(
+            serde_yaml::from_str::<RawAuthorRef>("name: Jane Doe\nemail: jane@example.com")
+                .unwrap(),
+            RawAuthorRef::Inline(RawAuthorMetadata {
+                name: String::from("Jane Doe"),
+                email: Some(String::from("jane@example.com")),
+                uri: None,
+            })
+        );
+    }
+
+    #[test]
+    fn explains_malformed_authors() {
+        let err = serde_yaml::from_str::<RawAuthorRef>("email: jane@example.com").unwrap_err();
+        assert!(err.to_string().contains("missing field `name`"), "{err}");
+
+        let err = serde_yaml::from_str::<RawAuthorRef>("[jane]").unwrap_err();
+        assert!(err.to_string().contains("an author must be"), "{err}");
+    }
+
+    #[test]
+    fn rejects_slugs_that_are_not_filenames() {
+        assert!(is_valid_slug("my-post"));
+
+        for slug in ["", "../escape", "a/b", "a\\b", ".hidden"] {
+            assert!(!is_valid_slug(slug), "{slug}");
+        }
     }
 }
@@ -0,0 +1,319 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{SecondsFormat, Utc};
+use eyre::{bail, WrapErr};
+use serde::Serialize;
+use tera::{Context, Tera};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::entry::Entry;
+use crate::error::Error;
+use crate::feed::Feed;
+use crate::gemtext;
+
+const MIMETYPE: &str = "application/epub+zip";
+const CONTAINER_XML: &str = include_str!("epub/container.xml");
+const PACKAGE_TEMPLATE: &str = include_str!("epub/content.opf.tera");
+const NAV_TEMPLATE: &str = include_str!("epub/nav.xhtml.tera");
+const CHAPTER_TEMPLATE: &str = include_str!("epub/chapter.xhtml.tera");
+
+// EPUB requires a language, so we use the code for an undetermined language if there isn't one.
+const UNDETERMINED_LANG: &str = "und";
+
+#[derive(Debug, Serialize)]
+struct ChapterTemplateData {
+    id: String,
+    file_name: String,
+    title: String,
+    date: String,
+    author: Option<String>,
+    lang: String,
+    body: String,
+}
+
+impl ChapterTemplateData {
+    fn new(number: usize, entry: Entry, book_lang: &str) -> Self {
+        Self {
+            id: format!("chapter-{number:04}"),
+            file_name: format!("chapter-{number:04}.xhtml"),
+            date: entry.date().to_rfc3339(),
+            body: gemtext::to_xhtml(&entry.body, &entry.url),
+            title: entry.metadata.title,
+            author: entry.metadata.author.map(|author| author.name),
+            lang: entry.metadata.lang.unwrap_or_else(|| book_lang.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BookTemplateData {
+    id: String,
+    title: String,
+    subtitle: Option<String>,
+    author: Option<String>,
+    rights: Option<String>,
+    lang: String,
+    modified: String,
+    chapters: Vec<ChapterTemplateData>,
+}
+
+impl From<Feed> for BookTemplateData {
+    fn from(feed: Feed) -> Self {
+        let lang = feed.lang.unwrap_or_else(|| UNDETERMINED_LANG.to_owned());
+
+        // Feeds are sorted newest first, but books should be read oldest first.
+        let chapters = feed
+            .entries
+            .into_iter()
+            .rev()
+            .enumerate()
+            .map(|(index, entry)| ChapterTemplateData::new(index + 1, entry, &lang))
+            .collect();
+
+        Self {
+            id: feed.id.to_string(),
+            title: feed.title,
+            subtitle: feed.subtitle,
+            author: feed.author.map(|author| author.name),
+            rights: feed.rights,
+            lang,
+            // EPUB requires this to be in UTC.
+            modified: feed
+                .updated
+                .with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            chapters,
+        }
+    }
+}
+
+fn load_templates() -> eyre::Result<Tera> {
+    let mut tera = Tera::default();
+
+    // These aren't `.xml` or `.html` files, so we need to tell Tera to XML-escape them.
+    tera.autoescape_on(vec![".opf", ".xhtml"]);
+
+    tera.add_raw_templates([
+        ("content.opf", PACKAGE_TEMPLATE),
+        ("nav.xhtml", NAV_TEMPLATE),
+        ("chapter.xhtml", CHAPTER_TEMPLATE),
+    ])
+    .wrap_err("The bundled EPUB templates are invalid. This is a bug.")?;
+
+    Ok(tera)
+}
+
+// Generate the files in an EPUB, other than the `mimetype` file, as pairs of paths and contents.
+fn book_files(feed: Feed) -> eyre::Result<Vec<(String, String)>> {
+    let tera = load_templates()?;
+    let book = BookTemplateData::from(feed);
+
+    let mut context = Context::new();
+    context.insert("book", &book);
+
+    let mut files = vec![
+        (
+            String::from("META-INF/container.xml"),
+            CONTAINER_XML.to_owned(),
+        ),
+        (
+            String::from("OEBPS/content.opf"),
+            tera.render("content.opf", &context)
+                .wrap_err("failed generating the EPUB package document")?,
+        ),
+        (
+            String::from("OEBPS/nav.xhtml"),
+            tera.render("nav.xhtml", &context)
+                .wrap_err("failed generating the EPUB table of contents")?,
+        ),
+    ];
+
+    for chapter in &book.chapters {
+        let mut context = Context::new();
+        context.insert("chapter", chapter);
+
+        files.push((
+            format!("OEBPS/{}", chapter.file_name),
+            tera.render("chapter.xhtml", &context)
+                .wrap_err("failed generating an EPUB chapter")?,
+        ));
+    }
+
+    Ok(files)
+}
+
+// Package the entries in a feed into an EPUB, with a chapter for each entry.
+pub fn export_epub(feed: Feed, output: &Path) -> eyre::Result<()> {
+    // A book without any chapters isn't a valid EPUB.
+    if feed.entries.is_empty() {
+        bail!(Error::EmptyCollection { name: feed.name });
+    }
+
+    let files = book_files(feed)?;
+
+    if let Some(parent_dir) = output.parent() {
+        fs::create_dir_all(parent_dir).wrap_err("failed creating parent directory")?;
+    }
+
+    let dest_file = File::create(output).wrap_err("failed creating EPUB file")?;
+    let mut zip = ZipWriter::new(dest_file);
+
+    // The `mimetype` file must come first and be uncompressed so that it can be used to identify
+    // the file type.
+    zip.start_file(
+        "mimetype",
+        FileOptions::default().compression_method(CompressionMethod::Stored),
+    )
+    .wrap_err("failed writing EPUB file")?;
+    zip.write_all(MIMETYPE.as_bytes())
+        .wrap_err("failed writing EPUB file")?;
+
+    for (path, contents) in files {
+        zip.start_file(
+            path,
+            FileOptions::default().compression_method(CompressionMethod::Deflated),
+        )
+        .wrap_err("failed writing EPUB file")?;
+        zip.write_all(contents.as_bytes())
+            .wrap_err("failed writing EPUB file")?;
+    }
+
+    zip.finish().wrap_err("failed writing EPUB file")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use roxmltree::{Document, ParsingOptions};
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::config::tests::temp_dir;
+    use crate::feed::tests::{entry, feed};
+
+    fn book() -> Feed {
+        let mut first = entry("first", "2024-01-01T00:00:00Z", &[]);
+        first.metadata.title = String::from("First");
+        first.body = String::from("# Intro\n=> next.gmi Next\n");
+
+        let mut second = entry("second", "2024-02-01T00:00:00Z", &[]);
+        second.metadata.title = String::from("A & B");
+        second.metadata.lang = Some(String::from("fr"));
+
+        let mut book = feed("main", vec![second, first]);
+        book.title = String::from("Example & Co");
+        book.lang = Some(String::from("en"));
+        book
+    }
+
+    // The XHTML files start with an HTML doctype.
+    fn parse(xml: &str) -> Document<'_> {
+        let options = ParsingOptions {
+            allow_dtd: true,
+            ..ParsingOptions::default()
+        };
+
+        Document::parse_with_options(xml, options).unwrap()
+    }
+
+    fn text_of<'a>(document: &'a Document, name: &str) -> Vec<&'a str> {
+        document
+            .descendants()
+            .filter(|node| node.tag_name().name() == name)
+            .map(|node| node.text().unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn renders_the_package_document() {
+        let files = book_files(book()).unwrap();
+        let paths = files
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            paths,
+            [
+                "META-INF/container.xml",
+                "OEBPS/content.opf",
+                "OEBPS/nav.xhtml",
+                "OEBPS/chapter-0001.xhtml",
+                "OEBPS/chapter-0002.xhtml",
+            ]
+        );
+
+        let package = parse(&files[1].1);
+        assert_eq!(text_of(&package, "identifier"), ["gemini://example.com/"]);
+        assert_eq!(text_of(&package, "title"), ["Example & Co"]);
+        assert_eq!(text_of(&package, "language"), ["en"]);
+        assert_eq!(text_of(&package, "meta"), ["2024-01-01T00:00:00Z"]);
+
+        let spine = package
+            .descendants()
+            .filter(|node| node.has_tag_name("itemref"))
+            .filter_map(|node| node.attribute("idref"))
+            .collect::<Vec<_>>();
+        assert_eq!(spine, ["nav", "chapter-0001", "chapter-0002"]);
+    }
+
+    #[test]
+    fn renders_the_table_of_contents_oldest_first() {
+        let files = book_files(book()).unwrap();
+        let nav = parse(&files[2].1);
+
+        assert_eq!(text_of(&nav, "a"), ["First", "A & B"]);
+    }
+
+    #[test]
+    fn renders_chapters() {
+        let files = book_files(book()).unwrap();
+
+        let first = parse(&files[3].1);
+        assert_eq!(first.root_element().attribute("lang"), Some("en"));
+        assert_eq!(text_of(&first, "h1"), ["First"]);
+        assert_eq!(text_of(&first, "h2"), ["Intro"]);
+
+        let link = first
+            .descendants()
+            .find(|node| node.has_tag_name("a"))
+            .unwrap();
+        assert_eq!(
+            link.attribute("href"),
+            Some("gemini://example.com/next.gmi")
+        );
+
+        let second = parse(&files[4].1);
+        assert_eq!(second.root_element().attribute("lang"), Some("fr"));
+        assert_eq!(text_of(&second, "h1"), ["A & B"]);
+    }
+
+    #[test]
+    fn writes_an_uncompressed_mimetype_first() {
+        let output = temp_dir("epub-export").join("book.epub");
+        export_epub(book(), &output).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        assert_eq!(archive.len(), 6);
+
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+    }
+
+    #[test]
+    fn rejects_empty_collections() {
+        let output = temp_dir("epub-empty").join("book.epub");
+        let err = export_epub(feed("notes", Vec::new()), &output).unwrap_err();
+
+        assert!(
+            matches!(err.downcast_ref::<Error>(), Some(Error::EmptyCollection { name }) if name == "notes"),
+            "{err:?}"
+        );
+        assert!(!output.exists());
+    }
+}
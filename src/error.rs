@@ -23,15 +23,70 @@ pub enum Error {
     #[error("There is already a post with this slug: {slug}")]
     PostAlreadyExists { slug: String },
 
+    #[error("There is no post with this slug: {slug}")]
+    NonexistentPost { slug: String },
+
+    #[error("This post is not a draft, so it has already been published: {slug}")]
+    PostNotDraft { slug: String },
+
+    #[error("This is not a valid slug for a post: {slug}")]
+    InvalidSlug { slug: String },
+
+    #[error("There was an issue loading the templates in `{path}`.\n\n{reason}")]
+    InvalidTemplatesDir { path: PathBuf, reason: String },
+
+    #[error("There is no collection named `{name}` in your gempost.yaml.")]
+    NonexistentCollection { name: String },
+
     #[error("There was an issue generating the index page.\n\n{reason}")]
     InvalidIndexPageTemplate { reason: String },
 
     #[error("There was an issue generating a post page.\n\n{reason}")]
     InvalidPostPageTemplate { path: PathBuf, reason: String },
 
+    #[error("There was an issue generating an author page.\n\n{reason}")]
+    InvalidAuthorPageTemplate { path: PathBuf, reason: String },
+
+    #[error("There was an issue generating the page at `{path}`.\n\n{reason}")]
+    InvalidPageTemplate { path: PathBuf, reason: String },
+
     #[error("The post path template in your gempost.yaml is invalid.\n\nTemplate: `{template}`\n\n{reason}")]
     InvalidPostPath { template: String, reason: String },
 
+    #[error("The author path template in your gempost.yaml is invalid.\n\nTemplate: `{template}`\n\n{reason}")]
+    InvalidAuthorPath { template: String, reason: String },
+
+    #[error("The language path template in your gempost.yaml is invalid.\n\nTemplate: `{template}`\n\n{reason}")]
+    InvalidLangPath { template: String, reason: String },
+
+    #[error(
+        "The `SOURCE_DATE_EPOCH` environment variable must be a Unix timestamp, but it is: {value}"
+    )]
+    InvalidSourceDateEpoch { value: String },
+
+    #[error("The {stage} hook `{command}` failed ({status}).")]
+    HookFailed {
+        stage: String,
+        command: String,
+        status: String,
+    },
+
+    #[error("The `{name}` processor failed on the post {path} ({status}).")]
+    ProcessorFailed {
+        name: String,
+        path: PathBuf,
+        status: String,
+    },
+
+    #[error("There is more than one post file for the same post: {first} and {second}. Each post can only have one body.")]
+    DuplicatePostFiles { first: PathBuf, second: PathBuf },
+
+    #[error("There is a problem with the Atom feed at `{path}`.\n\n{reason}")]
+    InvalidFeedFile { path: PathBuf, reason: String },
+
+    #[error("There are no published posts in the `{name}` collection to export.")]
+    EmptyCollection { name: String },
+
     #[error("The capsule URL you provided is not a valid URL: {url}")]
     InvalidCapsuleUrl { url: String },
 }
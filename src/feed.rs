@@ -1,13 +1,19 @@
 use std::cmp;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
-use chrono::{DateTime, FixedOffset, Local};
+use chrono::{DateTime, FixedOffset};
 use eyre::bail;
 use url::Url;
 
-use crate::config::{AuthorConfig, Config};
-use crate::entry::{Entry, PostLocation, PostLocationParams};
-use crate::template::{PostPathParams, PostPathTemplateData};
+use crate::clock;
+use crate::config::{AuthorConfig, CollectionConfig, Config, MAIN_COLLECTION_NAME};
+use crate::entry::{
+    AuthorMetadata, Entry, EntryNav, EntryTranslation, PostLocation, PostLocationParams,
+};
+use crate::error::Error;
+use crate::gemtext;
+use crate::template::{render_path, PostPathParams, PostPathTemplateData};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FeedAuthor {
@@ -26,8 +32,20 @@ impl From<AuthorConfig> for FeedAuthor {
     }
 }
 
+impl From<AuthorMetadata> for FeedAuthor {
+    fn from(value: AuthorMetadata) -> Self {
+        Self {
+            name: value.name,
+            email: value.email,
+            uri: value.uri,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Feed {
+    pub id: Url,
+    pub name: String,
     pub capsule_url: Url,
     pub feed_url: Url,
     pub index_url: Url,
@@ -36,72 +54,723 @@ pub struct Feed {
     pub subtitle: Option<String>,
     pub rights: Option<String>,
     pub author: Option<FeedAuthor>,
+    pub lang: Option<String>,
+    pub extra: BTreeMap<String, serde_yaml::Value>,
     pub entries: Vec<Entry>,
+    // Posts which aren't published, either because they're drafts or because they're scheduled to
+    // be published in the future.
+    pub drafts: Vec<Entry>,
 }
 
-impl Feed {
-    pub fn from_config(config: &Config, warn_handler: impl Fn(&str)) -> eyre::Result<Self> {
-        let locator = |params: PostLocationParams| -> eyre::Result<PostLocation> {
-            let mut post_url = config.url.clone();
+// Get the previous (older) and next (newer) entry for each entry in `entries`, which must be sorted
+// reverse-chronologically.
+fn neighbors(entries: &[&Entry]) -> Vec<EntryNav> {
+    let links = entries.iter().map(|entry| entry.link()).collect::<Vec<_>>();
 
-            let path_params = PostPathTemplateData::from(PostPathParams {
-                slug: params.slug.to_owned(),
-                published: params.metadata.published,
-            });
+    (0..links.len())
+        .map(|index| EntryNav {
+            previous: links.get(index + 1).cloned(),
+            next: index
+                .checked_sub(1)
+                .and_then(|newer_index| links.get(newer_index))
+                .cloned(),
+        })
+        .collect()
+}
 
-            let post_path = path_params.render(&config.post_path)?;
+// Populate the navigation links for each entry, both across the whole feed and within each of the
+// entry's categories.
+fn link_entries(entries: &mut [Entry]) {
+    let all_nav = neighbors(&entries.iter().collect::<Vec<_>>());
 
-            let mut url_segments = match post_url.path_segments_mut() {
-                Ok(segments) => segments,
-                Err(()) => bail!("capsule URL cannot be a base URL"),
-            };
+    let mut category_nav: BTreeMap<String, Vec<(usize, EntryNav)>> = BTreeMap::new();
 
-            let mut post_filepath = PathBuf::new();
+    let mut entries_by_category: BTreeMap<&str, Vec<(usize, &Entry)>> = BTreeMap::new();
 
-            for segment in post_path.split('/') {
-                url_segments.push(segment);
-                post_filepath.push(segment);
-            }
+    for (index, entry) in entries.iter().enumerate() {
+        for category in &entry.metadata.categories {
+            entries_by_category
+                .entry(category)
+                .or_default()
+                .push((index, entry));
+        }
+    }
+
+    for (category, indexed_entries) in entries_by_category {
+        let category_entries = indexed_entries
+            .iter()
+            .map(|(_, entry)| *entry)
+            .collect::<Vec<_>>();
+
+        category_nav.insert(
+            category.to_owned(),
+            indexed_entries
+                .iter()
+                .map(|(index, _)| *index)
+                .zip(neighbors(&category_entries))
+                .collect(),
+        );
+    }
+
+    for (entry, nav) in entries.iter_mut().zip(all_nav) {
+        entry.nav = nav;
+    }
 
-            drop(url_segments);
+    for (category, navs) in category_nav {
+        for (index, nav) in navs {
+            entries[index].category_nav.insert(category.clone(), nav);
+        }
+    }
+}
+
+// Populate the list of related entries for each entry, ranked by how many categories they share
+// with the entry. Ties are broken by recency. Entries which share no categories are not related.
+fn relate_entries(entries: &mut [Entry], limit: usize) {
+    let related = entries
+        .iter()
+        .map(|entry| {
+            let mut candidates = entries
+                .iter()
+                .filter(|other| !std::ptr::eq(*other, entry))
+                .map(|other| {
+                    let shared = other
+                        .metadata
+                        .categories
+                        .iter()
+                        .filter(|category| entry.metadata.categories.contains(category))
+                        .count();
+
+                    (shared, other)
+                })
+                .filter(|(shared, _)| *shared > 0)
+                .collect::<Vec<_>>();
+
+            candidates
+                .sort_by_key(|(shared, other)| (cmp::Reverse(*shared), cmp::Reverse(other.date())));
 
-            Ok(PostLocation {
-                url: post_url,
-                path: post_filepath,
+            candidates
+                .into_iter()
+                .take(limit)
+                .map(|(_, other)| other.link())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    for (entry, related) in entries.iter_mut().zip(related) {
+        entry.related = related;
+    }
+}
+
+// The posts written by a single author.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorFeed {
+    pub author: FeedAuthor,
+    pub index_path: Option<String>,
+    pub feed_path: Option<String>,
+    pub feed: Feed,
+}
+
+// Authors who aren't in the `authors` section of the config file have no handle, so `handle` is
+// an empty string for them.
+fn author_path(
+    template: Option<&str>,
+    author: &FeedAuthor,
+    handle: Option<&str>,
+) -> eyre::Result<Option<String>> {
+    let values = [
+        ("author", author.name.as_str()),
+        ("handle", handle.unwrap_or_default()),
+    ];
+
+    template
+        .map(|template| {
+            render_path(template, &values, |reason| Error::InvalidAuthorPath {
+                template: template.to_owned(),
+                reason,
             })
-        };
+        })
+        .transpose()
+}
 
-        let mut entries = Entry::from_posts(&config.posts_dir, locator, warn_handler)?;
+// The posts written in a single language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LangFeed {
+    pub index_path: Option<String>,
+    pub feed_path: Option<String>,
+    pub feed: Feed,
+}
 
-        // Sort entries in reverse-chronological order by publish time or, if there is no publish
-        // time by last updated time.
-        entries.sort_by_key(|entry| {
-            cmp::Reverse(entry.metadata.published.unwrap_or(entry.metadata.updated))
+fn lang_path(template: Option<&str>, lang: &str) -> eyre::Result<Option<String>> {
+    template
+        .map(|template| {
+            render_path(template, &[("lang", lang)], |reason| {
+                Error::InvalidLangPath {
+                    template: template.to_owned(),
+                    reason,
+                }
+            })
+        })
+        .transpose()
+}
+
+// Populate the list of translations for each entry. Entries are translations of each other when
+// one has a `translation_of` which is the ID of the other, or when both are translations of the
+// same entry. A translation of a translation is grouped with the original entry.
+fn translate_entries(entries: &mut [Entry]) {
+    let translation_of = entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.metadata.id.as_str(),
+                entry.metadata.translation_of.as_deref(),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut groups: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        // Follow the chain of `translation_of` IDs back to the original entry. If the chain loops
+        // back on itself, there is no original, so we use the smallest ID in the loop so that
+        // every entry in it ends up in the same group.
+        let mut original_id = entry.metadata.id.as_str();
+        let mut chain = Vec::new();
+
+        while let Some(Some(next_id)) = translation_of.get(original_id) {
+            if let Some(loop_start) = chain.iter().position(|id| *id == original_id) {
+                original_id = chain[loop_start..]
+                    .iter()
+                    .min()
+                    .copied()
+                    .unwrap_or(original_id);
+                break;
+            }
+
+            chain.push(original_id);
+            original_id = next_id;
+        }
+
+        groups.entry(original_id).or_default().push(index);
+    }
+
+    let groups = groups.into_values().collect::<Vec<_>>();
+
+    for group in groups {
+        for &index in &group {
+            let translations = group
+                .iter()
+                .filter(|&&other_index| other_index != index)
+                .map(|&other_index| EntryTranslation {
+                    lang: entries[other_index].metadata.lang.clone(),
+                    title: entries[other_index].metadata.title.clone(),
+                    url: entries[other_index].url.clone(),
+                })
+                .collect();
+
+            entries[index].translations = translations;
+        }
+    }
+}
+
+// Work out the URL of a post and the path of its page in the output directory from the collection's
+// post path template.
+pub fn locate_post(
+    config: &Config,
+    collection: &CollectionConfig,
+    params: PostLocationParams,
+) -> eyre::Result<PostLocation> {
+    let mut post_url = config.url.clone();
+
+    let path_params = PostPathTemplateData::from(PostPathParams {
+        slug: params.slug.to_owned(),
+        published: params.metadata.published,
+    });
+
+    let post_path = path_params.render(&collection.post_path)?;
+
+    let mut url_segments = match post_url.path_segments_mut() {
+        Ok(segments) => segments,
+        Err(()) => bail!("capsule URL cannot be a base URL"),
+    };
+
+    let mut post_filepath = PathBuf::new();
+
+    for segment in post_path.split('/') {
+        url_segments.push(segment);
+        post_filepath.push(segment);
+    }
+
+    drop(url_segments);
+
+    Ok(PostLocation {
+        url: post_url,
+        path: post_filepath,
+    })
+}
+
+impl Feed {
+    pub fn from_config(
+        config: &Config,
+        collection: &CollectionConfig,
+        warn_handler: impl Fn(&str),
+    ) -> eyre::Result<Self> {
+        let locator = |params: PostLocationParams| locate_post(config, collection, params);
+
+        let all_entries = Entry::from_posts(&collection.posts_dir, config, locator, warn_handler)?;
+
+        let now = clock::now()?;
+
+        // We do not publish draft posts, or posts scheduled to be published in the future. Those are
+        // held back until a build after their publish time.
+        let (drafts, mut entries): (Vec<_>, Vec<_>) = all_entries.into_iter().partition(|entry| {
+            entry.metadata.draft
+                || entry
+                    .metadata
+                    .published
+                    .is_some_and(|published| published > now)
         });
 
-        // Get the time the most recently updated post was updated.
+        // Split the excerpt off from posts which have an excerpt marker, removing the marker.
+        if let Some(marker) = &config.excerpt_marker {
+            for entry in entries.iter_mut() {
+                if let Some((excerpt, body)) = gemtext::split_at_marker(&entry.body, marker) {
+                    entry.excerpt = Some(excerpt);
+                    entry.body = body;
+                }
+            }
+        }
+
+        // Generate summaries for posts that don't have one.
+        if config.summary_length > 0 {
+            for entry in entries.iter_mut() {
+                if entry.metadata.summary.is_none() {
+                    entry.metadata.summary = gemtext::summarize(&entry.body, config.summary_length);
+                }
+            }
+        }
+
+        // Sort entries in reverse-chronological order by publish time or, if there is no publish
+        // time by last updated time. Entries with the same time are sorted by path so the order
+        // doesn't depend on the order we read them from the filesystem.
+        entries.sort_by(|a, b| b.date().cmp(&a.date()).then_with(|| a.path.cmp(&b.path)));
+
+        link_entries(&mut entries);
+        relate_entries(&mut entries, config.related_posts_limit);
+        translate_entries(&mut entries);
+
+        // Get the time the most recently updated post was updated. If there are no posts, use the
+        // current time.
         let last_updated = entries
             .iter()
-            .max_by_key(|entry| entry.metadata.updated)
             .map(|entry| entry.metadata.updated)
-            .unwrap_or_else(|| Local::now().fixed_offset());
+            .max()
+            .unwrap_or(now);
 
         let mut feed_url = config.url.clone();
-        feed_url.set_path(&config.feed_path);
+        feed_url.set_path(&collection.feed_path);
 
         let mut index_url = config.url.clone();
-        index_url.set_path(&config.index_path);
+        index_url.set_path(&collection.index_path);
+
+        // The main feed has always used the capsule URL as its ID, so we can't change it without
+        // making feed readers think it's a different feed. Each other collection needs its own
+        // unique ID.
+        let id = if collection.name == MAIN_COLLECTION_NAME {
+            config.url.clone()
+        } else {
+            feed_url.clone()
+        };
 
         Ok(Feed {
+            id,
+            name: collection.name.clone(),
             capsule_url: config.url.clone(),
             feed_url,
             index_url,
-            title: config.title.clone(),
+            title: collection.title.clone(),
             updated: last_updated,
-            subtitle: config.subtitle.clone(),
-            rights: config.rights.clone(),
-            author: config.author.as_ref().cloned().map(Into::into),
+            subtitle: collection.subtitle.clone(),
+            rights: collection.rights.clone(),
+            author: collection.author.as_ref().cloned().map(Into::into),
+            lang: None,
+            extra: config.extra.clone(),
             entries,
+            drafts,
         })
     }
+
+    // Create a feed containing only some of the entries in this feed, served at different URLs. If
+    // the new feed has no index page or Atom feed of its own, it links to the ones for this feed.
+    fn subset(
+        &self,
+        config: &Config,
+        entries: Vec<Entry>,
+        index_path: Option<&str>,
+        feed_path: Option<&str>,
+    ) -> Feed {
+        let mut index_url = self.index_url.clone();
+        if let Some(path) = index_path {
+            index_url = config.url.clone();
+            index_url.set_path(path);
+        }
+
+        let mut feed_url = self.feed_url.clone();
+        if let Some(path) = feed_path {
+            feed_url = config.url.clone();
+            feed_url.set_path(path);
+        }
+
+        let last_updated = entries
+            .iter()
+            .map(|entry| entry.metadata.updated)
+            .max()
+            .unwrap_or(self.updated);
+
+        Feed {
+            // Each feed needs its own unique ID.
+            id: feed_url.clone(),
+            name: self.name.clone(),
+            capsule_url: self.capsule_url.clone(),
+            feed_url,
+            index_url,
+            title: self.title.clone(),
+            updated: last_updated,
+            subtitle: self.subtitle.clone(),
+            rights: self.rights.clone(),
+            author: self.author.clone(),
+            lang: self.lang.clone(),
+            extra: self.extra.clone(),
+            entries,
+            drafts: Vec::new(),
+        }
+    }
+
+    // Split this feed into a separate feed for each author. Posts without an author are attributed
+    // to the primary author of the capsule. When neither the author index pages nor the author
+    // feeds are enabled in the config, this returns nothing.
+    pub fn author_feeds(&self, config: &Config) -> eyre::Result<Vec<AuthorFeed>> {
+        if config.author_index_path.is_none() && config.author_feed_path.is_none() {
+            return Ok(Vec::new());
+        }
+
+        // Authors in the `authors` section of the config file are grouped by their handle, using
+        // their details from the config file. Other authors are grouped by name, unless they have
+        // the same name as an author in the config file.
+        let mut entries_by_author: BTreeMap<(Option<String>, String), (FeedAuthor, Vec<Entry>)> =
+            BTreeMap::new();
+
+        for entry in &self.entries {
+            let (handle, author) = match (&entry.metadata.author, &self.author) {
+                (Some(entry_author), _) => (
+                    entry_author.handle.clone(),
+                    FeedAuthor::from(entry_author.clone()),
+                ),
+                (None, Some(feed_author)) => (None, feed_author.clone()),
+                (None, None) => continue,
+            };
+
+            let handle = handle.or_else(|| {
+                config
+                    .authors
+                    .iter()
+                    .find(|(_, registered)| registered.name == author.name)
+                    .map(|(handle, _)| handle.clone())
+            });
+
+            let registered = handle
+                .as_deref()
+                .and_then(|handle| config.authors.get_key_value(handle));
+
+            let (key, author) = match registered {
+                Some((handle, registered)) => (
+                    (Some(handle.clone()), String::new()),
+                    registered.clone().into(),
+                ),
+                // Entries are sorted newest first, so we use the author details from the most
+                // recent post.
+                None => ((None, author.name.clone()), author),
+            };
+
+            entries_by_author
+                .entry(key)
+                .or_insert_with(|| (author, Vec::new()))
+                .1
+                .push(entry.clone());
+        }
+
+        let mut author_feeds = Vec::new();
+
+        for ((handle, _), (author, entries)) in entries_by_author {
+            let handle = handle.as_deref();
+            let index_path = author_path(config.author_index_path.as_deref(), &author, handle)?;
+            let feed_path = author_path(config.author_feed_path.as_deref(), &author, handle)?;
+
+            let mut feed =
+                self.subset(config, entries, index_path.as_deref(), feed_path.as_deref());
+            feed.author = Some(author.clone());
+
+            author_feeds.push(AuthorFeed {
+                author,
+                index_path,
+                feed_path,
+                feed,
+            });
+        }
+
+        Ok(author_feeds)
+    }
+
+    // Split this feed into a separate feed for each language. Posts without a language are not
+    // included in any of them. When neither the language index pages nor the language feeds are
+    // enabled in the config, this returns nothing.
+    pub fn lang_feeds(&self, config: &Config) -> eyre::Result<Vec<LangFeed>> {
+        if config.lang_index_path.is_none() && config.lang_feed_path.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries_by_lang: BTreeMap<String, Vec<Entry>> = BTreeMap::new();
+
+        for entry in &self.entries {
+            if let Some(lang) = &entry.metadata.lang {
+                entries_by_lang
+                    .entry(lang.clone())
+                    .or_default()
+                    .push(entry.clone());
+            }
+        }
+
+        let mut lang_feeds = Vec::new();
+
+        for (lang, entries) in entries_by_lang {
+            let index_path = lang_path(config.lang_index_path.as_deref(), &lang)?;
+            let feed_path = lang_path(config.lang_feed_path.as_deref(), &lang)?;
+
+            let mut feed =
+                self.subset(config, entries, index_path.as_deref(), feed_path.as_deref());
+            feed.lang = Some(lang);
+
+            lang_feeds.push(LangFeed {
+                index_path,
+                feed_path,
+                feed,
+            });
+        }
+
+        Ok(lang_feeds)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::tests::{read_config, temp_dir};
+    use crate::entry::{EntryLink, EntryMetadata};
+
+    // An entry with the given slug and date. Other modules' tests use this to build feeds.
+    pub(crate) fn entry(slug: &str, date: &str, categories: &[&str]) -> Entry {
+        Entry {
+            slug: slug.to_owned(),
+            metadata: EntryMetadata {
+                id: format!("urn:{slug}"),
+                title: slug.to_uppercase(),
+                updated: DateTime::parse_from_rfc3339(date).unwrap(),
+                summary: None,
+                published: None,
+                author: None,
+                contributors: Vec::new(),
+                rights: None,
+                lang: None,
+                categories: categories
+                    .iter()
+                    .map(|&category| category.to_owned())
+                    .collect(),
+                draft: false,
+                noindex: false,
+                translation_of: None,
+                processors: Vec::new(),
+                changelog: Vec::new(),
+                aliases: Vec::new(),
+                extra: BTreeMap::new(),
+            },
+            body: String::new(),
+            excerpt: None,
+            url: Url::parse(&format!("gemini://example.com/{slug}.gmi")).unwrap(),
+            path: PathBuf::from(format!("{slug}.gmi")),
+            nav: EntryNav::default(),
+            category_nav: BTreeMap::new(),
+            related: Vec::new(),
+            translations: Vec::new(),
+        }
+    }
+
+    pub(crate) fn feed(name: &str, entries: Vec<Entry>) -> Feed {
+        let capsule_url = Url::parse("gemini://example.com/").unwrap();
+
+        Feed {
+            id: capsule_url.clone(),
+            name: name.to_owned(),
+            capsule_url: capsule_url.clone(),
+            feed_url: capsule_url.join("atom.xml").unwrap(),
+            index_url: capsule_url.join("index.gmi").unwrap(),
+            title: String::from("Example"),
+            updated: DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap(),
+            subtitle: None,
+            rights: None,
+            author: None,
+            lang: None,
+            extra: BTreeMap::new(),
+            entries,
+            drafts: Vec::new(),
+        }
+    }
+
+    fn titles<'a>(links: impl IntoIterator<Item = &'a EntryLink>) -> Vec<&'a str> {
+        links.into_iter().map(|link| link.title.as_str()).collect()
+    }
+
+    #[test]
+    fn links_neighboring_entries() {
+        let mut entries = vec![
+            entry("c", "2024-03-01T00:00:00Z", &["rust"]),
+            entry("b", "2024-02-01T00:00:00Z", &[]),
+            entry("a", "2024-01-01T00:00:00Z", &["rust"]),
+        ];
+
+        link_entries(&mut entries);
+
+        assert_eq!(titles(&entries[0].nav.previous), ["B"]);
+        assert_eq!(titles(&entries[0].nav.next), Vec::<&str>::new());
+        assert_eq!(titles(&entries[1].nav.previous), ["A"]);
+        assert_eq!(titles(&entries[1].nav.next), ["C"]);
+
+        // Within a category, entries skip over the entries that aren't in it.
+        assert_eq!(titles(&entries[0].category_nav["rust"].previous), ["A"]);
+        assert_eq!(titles(&entries[2].category_nav["rust"].next), ["C"]);
+        assert!(entries[1].category_nav.is_empty());
+    }
+
+    #[test]
+    fn relates_entries_by_shared_categories() {
+        let mut entries = vec![
+            entry("d", "2024-04-01T00:00:00Z", &["rust"]),
+            entry("c", "2024-03-01T00:00:00Z", &["rust", "gemini"]),
+            entry("b", "2024-02-01T00:00:00Z", &["cooking"]),
+            entry("a", "2024-01-01T00:00:00Z", &["rust", "gemini"]),
+        ];
+
+        relate_entries(&mut entries, 5);
+
+        // More shared categories rank first, then newer entries.
+        assert_eq!(titles(&entries[3].related), ["C", "D"]);
+        assert_eq!(titles(&entries[0].related), ["C", "A"]);
+        assert!(entries[2].related.is_empty());
+
+        relate_entries(&mut entries, 1);
+        assert_eq!(titles(&entries[3].related), ["C"]);
+    }
+
+    fn translation_titles(entry: &Entry) -> Vec<&str> {
+        entry
+            .translations
+            .iter()
+            .map(|translation| translation.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn groups_translations_of_the_same_entry() {
+        let mut entries = vec![
+            entry("a", "2024-01-01T00:00:00Z", &[]),
+            entry("b", "2024-01-01T00:00:00Z", &[]),
+            entry("c", "2024-01-01T00:00:00Z", &[]),
+            entry("d", "2024-01-01T00:00:00Z", &[]),
+        ];
+        entries[1].metadata.translation_of = Some(String::from("urn:a"));
+        entries[2].metadata.translation_of = Some(String::from("urn:a"));
+
+        translate_entries(&mut entries);
+
+        assert_eq!(translation_titles(&entries[0]), ["B", "C"]);
+        assert_eq!(translation_titles(&entries[1]), ["A", "C"]);
+        assert_eq!(translation_titles(&entries[2]), ["A", "B"]);
+        assert!(entries[3].translations.is_empty());
+    }
+
+    #[test]
+    fn groups_translations_of_translations_with_the_original() {
+        let mut entries = vec![
+            entry("a", "2024-01-01T00:00:00Z", &[]),
+            entry("b", "2024-01-01T00:00:00Z", &[]),
+            entry("c", "2024-01-01T00:00:00Z", &[]),
+        ];
+        entries[1].metadata.translation_of = Some(String::from("urn:a"));
+        entries[2].metadata.translation_of = Some(String::from("urn:b"));
+
+        translate_entries(&mut entries);
+
+        assert_eq!(translation_titles(&entries[0]), ["B", "C"]);
+        assert_eq!(translation_titles(&entries[2]), ["A", "B"]);
+    }
+
+    #[test]
+    fn groups_translations_which_loop() {
+        let mut entries = vec![
+            entry("a", "2024-01-01T00:00:00Z", &[]),
+            entry("b", "2024-01-01T00:00:00Z", &[]),
+            entry("c", "2024-01-01T00:00:00Z", &[]),
+        ];
+        entries[0].metadata.translation_of = Some(String::from("urn:b"));
+        entries[1].metadata.translation_of = Some(String::from("urn:a"));
+        entries[2].metadata.translation_of = Some(String::from("urn:b"));
+
+        translate_entries(&mut entries);
+
+        assert_eq!(translation_titles(&entries[0]), ["B", "C"]);
+        assert_eq!(translation_titles(&entries[1]), ["A", "C"]);
+    }
+
+    #[test]
+    fn holds_back_drafts_and_scheduled_posts() {
+        let dir = temp_dir("feed-scheduled");
+        let posts_dir = dir.join("posts");
+        fs::create_dir_all(&posts_dir).unwrap();
+        let config = read_config(
+            &dir,
+            &format!(
+                "title: Example\nurl: gemini://example.com/\nposts_dir: \"{}\"\n",
+                posts_dir.to_string_lossy()
+            ),
+        )
+        .unwrap();
+
+        for (slug, extra) in [
+            ("published", "published: \"2024-01-01T00:00:00Z\"\n"),
+            ("scheduled", "published: \"2999-01-01T00:00:00Z\"\n"),
+            ("draft", "draft: true\n"),
+        ] {
+            fs::write(posts_dir.join(format!("{slug}.gmi")), "Hello\n").unwrap();
+            fs::write(
+                posts_dir.join(format!("{slug}.yaml")),
+                format!(
+                    "id: \"urn:{slug}\"\ntitle: \"{slug}\"\nupdated: \"2024-01-01T00:00:00Z\"\n{extra}"
+                ),
+            )
+            .unwrap();
+        }
+
+        let feed = Feed::from_config(&config, &config.main_collection(), |_| {}).unwrap();
+
+        let slugs = |entries: &[Entry]| {
+            let mut slugs = entries
+                .iter()
+                .map(|entry| entry.slug.clone())
+                .collect::<Vec<_>>();
+            slugs.sort();
+            slugs
+        };
+        assert_eq!(slugs(&feed.entries), ["published"]);
+        assert_eq!(slugs(&feed.drafts), ["draft", "scheduled"]);
+    }
 }
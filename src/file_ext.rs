@@ -0,0 +1,5 @@
+// The extensions of the files gempost reads from the posts directory.
+
+pub const POST_FILE_EXT: &str = "gmi";
+pub const METADATA_FILE_EXT: &str = "yaml";
+pub const MARKDOWN_FILE_EXT: &str = "md";
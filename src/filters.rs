@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use tera::{to_value, try_get_value, Tera, Value};
+
+use crate::clock;
+use crate::gemtext;
+use crate::template::CollectionsTemplateData;
+
+// The average reading speed used to estimate reading time, in words per minute.
+const DEFAULT_WORDS_PER_MINUTE: u64 = 200;
+
+// The number of lines in an excerpt when no number is given.
+const DEFAULT_EXCERPT_LINES: u64 = 5;
+
+fn word_count(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let body = try_get_value!("word_count", "value", String, value);
+    Ok(to_value(gemtext::word_count(&body))?)
+}
+
+fn reading_time(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let body = try_get_value!("reading_time", "value", String, value);
+
+    let words_per_minute = match args.get("words_per_minute") {
+        Some(value) => try_get_value!("reading_time", "words_per_minute", u64, value),
+        None => DEFAULT_WORDS_PER_MINUTE,
+    };
+
+    if words_per_minute == 0 {
+        return Err(tera::Error::msg(
+            "Filter `reading_time` received `words_per_minute` of 0, but it must be positive.",
+        ));
+    }
+
+    let words = gemtext::word_count(&body) as u64;
+
+    // Round up, so that short posts take at least a minute to read.
+    Ok(to_value(words.div_ceil(words_per_minute).max(1))?)
+}
+
+fn excerpt(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let body = try_get_value!("excerpt", "value", String, value);
+
+    let lines = match args.get("lines") {
+        Some(value) => try_get_value!("excerpt", "lines", u64, value),
+        None => DEFAULT_EXCERPT_LINES,
+    };
+
+    Ok(to_value(gemtext::excerpt(
+        &body,
+        usize::try_from(lines).unwrap_or(usize::MAX),
+    ))?)
+}
+
+fn gmi_escape(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = try_get_value!("gmi_escape", "value", String, value);
+    Ok(to_value(gemtext::escape(&text))?)
+}
+
+fn strip_headings(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let body = try_get_value!("strip_headings", "value", String, value);
+    Ok(to_value(gemtext::strip_headings(&body))?)
+}
+
+// This replaces Tera's built-in `now` function so that it respects `SOURCE_DATE_EPOCH`.
+fn now(args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let timestamp = match args.get("timestamp") {
+        Some(value) => try_get_value!("now", "timestamp", bool, value),
+        None => false,
+    };
+
+    let utc = match args.get("utc") {
+        Some(value) => try_get_value!("now", "utc", bool, value),
+        None => false,
+    };
+
+    let time = clock::now().map_err(|err| tera::Error::msg(err.to_string()))?;
+
+    if timestamp {
+        return Ok(to_value(time.timestamp())?);
+    }
+
+    if utc {
+        return Ok(to_value(time.with_timezone(&Utc).to_rfc3339())?);
+    }
+
+    Ok(to_value(time.to_rfc3339())?)
+}
+
+// Register the gemtext-aware filters and functions with a Tera instance. The `collections` are used
+// to look up other entries by their ID.
+pub fn register_filters(
+    tera: &mut Tera,
+    collections: &CollectionsTemplateData,
+) -> eyre::Result<()> {
+    tera.register_filter("word_count", word_count);
+    tera.register_filter("reading_time", reading_time);
+    tera.register_filter("excerpt", excerpt);
+    tera.register_filter("gmi_escape", gmi_escape);
+    tera.register_filter("strip_headings", strip_headings);
+    tera.register_function("now", now);
+
+    let mut entries_by_id = HashMap::new();
+
+    for entry in collections.values().flat_map(|feed| &feed.entries) {
+        entries_by_id.insert(entry.id.clone(), to_value(entry)?);
+    }
+
+    tera.register_function(
+        "get_entry",
+        move |args: &HashMap<String, Value>| -> tera::Result<Value> {
+            let id = match args.get("id") {
+                Some(value) => try_get_value!("get_entry", "id", String, value),
+                None => {
+                    return Err(tera::Error::msg(
+                        "Function `get_entry` was called without an `id` argument.",
+                    ))
+                }
+            };
+
+            match entries_by_id.get(&id) {
+                Some(entry) => Ok(entry.clone()),
+                None => Err(tera::Error::msg(format!(
+                    "Function `get_entry` could not find a post with the ID `{id}`."
+                ))),
+            }
+        },
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tera::Context;
+
+    use super::*;
+    use crate::feed::tests::{entry, feed};
+    use crate::template::FeedTemplateData;
+
+    fn render(template: &str, context: &Context) -> tera::Result<String> {
+        let mut main_entry = entry("hello", "2024-01-01T00:00:00Z", &[]);
+        main_entry.metadata.title = String::from("Hello, world!");
+
+        let collections = CollectionsTemplateData::from([(
+            String::from("main"),
+            FeedTemplateData::from(feed("main", vec![main_entry])),
+        )]);
+
+        let mut tera = Tera::default();
+        register_filters(&mut tera, &collections).unwrap();
+        tera.render_str(template, context)
+    }
+
+    fn render_body(template: &str, body: &str) -> String {
+        let mut context = Context::new();
+        context.insert("body", body);
+        render(template, &context).unwrap()
+    }
+
+    #[test]
+    fn escapes_gemtext_lines() {
+        assert_eq!(
+            render_body(
+                "{{ body | gmi_escape | safe }}",
+                "=> /link\n# Heading\nText\n```\n"
+            ),
+            " => /link\n # Heading\nText\n ```\n"
+        );
+        assert_eq!(render_body("{{ body | gmi_escape | safe }}", "* a"), " * a");
+    }
+
+    #[test]
+    fn takes_excerpts() {
+        let body = "# Title\n\nOne\n```\ncode\nmore code\n```\n";
+
+        assert_eq!(
+            render_body("{{ body | excerpt(lines=2) | safe }}", body),
+            "# Title\n\n"
+        );
+        assert_eq!(
+            render_body("{{ body | excerpt(lines=5) | safe }}", body),
+            "# Title\n\nOne\n```\ncode\n```\n"
+        );
+        assert_eq!(
+            render_body("{{ body | excerpt | safe }}", body),
+            "# Title\n\nOne\n```\ncode\n```\n"
+        );
+    }
+
+    #[test]
+    fn counts_words() {
+        let body = "# Two words\n=> /url Link label\n```\nnot counted\n```\n* One\n> Quoted text\n";
+
+        assert_eq!(render_body("{{ body | word_count }}", body), "7");
+        assert_eq!(
+            render_body("{{ body | reading_time(words_per_minute=3) }}", body),
+            "3"
+        );
+        assert_eq!(render_body("{{ body | reading_time }}", ""), "1");
+    }
+
+    #[test]
+    fn strips_headings() {
+        assert_eq!(
+            render_body(
+                "{{ body | strip_headings | safe }}",
+                "# Title\nText\n## Section\nMore text\n"
+            ),
+            "Text\nMore text\n"
+        );
+    }
+
+    #[test]
+    fn gets_entries_by_id() {
+        assert_eq!(
+            render_body(
+                "{% set post = get_entry(id=\"urn:hello\") %}{{ post.title }}",
+                ""
+            ),
+            "Hello, world!"
+        );
+
+        let err = render("{{ get_entry(id=\"urn:missing\") }}", &Context::new()).unwrap_err();
+        assert!(
+            format!("{err:?}").contains("could not find a post with the ID `urn:missing`"),
+            "{err:?}"
+        );
+    }
+}
@@ -0,0 +1,486 @@
+use url::Url;
+
+// The prefix which marks a line as a preformatting toggle line.
+const PREFORMAT_TOGGLE: &str = "```";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind<'a> {
+    Text(&'a str),
+    Link {
+        url: &'a str,
+        label: Option<&'a str>,
+    },
+    Heading {
+        level: usize,
+        text: &'a str,
+    },
+    ListItem(&'a str),
+    Quote(&'a str),
+    PreformatToggle {
+        alt: &'a str,
+    },
+    Preformatted(&'a str),
+}
+
+// A single line of a gemtext document, along with its original text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub raw: &'a str,
+    pub kind: LineKind<'a>,
+}
+
+fn parse_line(raw: &str) -> LineKind<'_> {
+    if let Some(rest) = raw.strip_prefix("=>") {
+        let rest = rest.trim_start();
+        let (url, label) = match rest.find(char::is_whitespace) {
+            Some(index) => (
+                &rest[..index],
+                Some(rest[index..].trim()).filter(|l| !l.is_empty()),
+            ),
+            None => (rest, None),
+        };
+
+        return LineKind::Link { url, label };
+    }
+
+    if let Some(alt) = raw.strip_prefix(PREFORMAT_TOGGLE) {
+        return LineKind::PreformatToggle { alt: alt.trim() };
+    }
+
+    for (prefix, level) in [("###", 3), ("##", 2), ("#", 1)] {
+        if let Some(text) = raw.strip_prefix(prefix) {
+            return LineKind::Heading {
+                level,
+                text: text.trim(),
+            };
+        }
+    }
+
+    if let Some(text) = raw.strip_prefix("* ") {
+        return LineKind::ListItem(text.trim());
+    }
+
+    if let Some(text) = raw.strip_prefix('>') {
+        return LineKind::Quote(text.trim());
+    }
+
+    LineKind::Text(raw)
+}
+
+// Split a gemtext document into lines and determine the type of each line.
+pub fn parse(body: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut preformatted = false;
+
+    for raw in body.lines() {
+        let kind = if raw.starts_with(PREFORMAT_TOGGLE) {
+            preformatted = !preformatted;
+            parse_line(raw)
+        } else if preformatted {
+            LineKind::Preformatted(raw)
+        } else {
+            parse_line(raw)
+        };
+
+        lines.push(Line { raw, kind });
+    }
+
+    lines
+}
+
+// Join lines back into a gemtext document.
+fn join(lines: &[Line]) -> String {
+    let mut output = lines
+        .iter()
+        .map(|line| line.raw)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if !output.is_empty() {
+        output.push('\n');
+    }
+
+    output
+}
+
+// Replace the URLs of link lines using `rewrite`, which returns the new URL for a link or `None` to
+// leave it as-is. The rest of the document is left exactly as it was. This returns `None` if no
+// links were changed.
+pub fn rewrite_links(body: &str, rewrite: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let mut output = String::with_capacity(body.len());
+    let mut changed = false;
+
+    // `parse` returns one line for each line of the body, but without the line endings.
+    for (line, raw) in parse(body).iter().zip(body.split_inclusive('\n')) {
+        if let LineKind::Link { url, .. } = line.kind {
+            if let Some(new_url) = rewrite(url) {
+                // The URL is the first thing after the `=>`, so we don't mistake the `=>` or the
+                // label for it.
+                let url_start = line.raw.len() - line.raw["=>".len()..].trim_start().len();
+
+                output.push_str(&line.raw[..url_start]);
+                output.push_str(&new_url);
+                output.push_str(&raw[url_start + url.len()..]);
+                changed = true;
+                continue;
+            }
+        }
+
+        output.push_str(raw);
+    }
+
+    changed.then_some(output)
+}
+
+// Count the words a reader would read, which excludes link URLs, line type markers, and the
+// contents of preformatted blocks.
+pub fn word_count(body: &str) -> usize {
+    parse(body)
+        .iter()
+        .map(|line| match line.kind {
+            LineKind::Text(text)
+            | LineKind::Heading { text, .. }
+            | LineKind::ListItem(text)
+            | LineKind::Quote(text) => text.split_whitespace().count(),
+            LineKind::Link { label, .. } => label.unwrap_or_default().split_whitespace().count(),
+            LineKind::PreformatToggle { .. } | LineKind::Preformatted(_) => 0,
+        })
+        .sum()
+}
+
+// Get the first `count` lines of a document. If this would cut off a preformatted block, the block
+// is closed so it doesn't swallow whatever follows the excerpt.
+pub fn excerpt(body: &str, count: usize) -> String {
+    let lines = parse(body);
+    let excerpt_lines = &lines[..count.min(lines.len())];
+
+    let mut output = join(excerpt_lines);
+
+    let open_toggles = excerpt_lines
+        .iter()
+        .filter(|line| matches!(line.kind, LineKind::PreformatToggle { .. }))
+        .count();
+
+    if open_toggles % 2 == 1 {
+        output.push_str(PREFORMAT_TOGGLE);
+        output.push('\n');
+    }
+
+    output
+}
+
+// Remove all heading lines from a document.
+pub fn strip_headings(body: &str) -> String {
+    let lines = parse(body)
+        .into_iter()
+        .filter(|line| !matches!(line.kind, LineKind::Heading { .. }))
+        .collect::<Vec<_>>();
+
+    join(&lines)
+}
+
+// Escape text so that none of its lines are interpreted as anything other than a plain text line
+// when inserted into a gemtext document. Lines which would otherwise be a link, heading, list item,
+// quote, or preformatting toggle are prefixed with a space. Line endings are kept as they are.
+pub fn escape(text: &str) -> String {
+    text.split('\n')
+        .map(|line| match parse_line(line) {
+            LineKind::Text(_) => line.to_owned(),
+            _ => format!(" {line}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Truncate text to at most `max_chars` characters, breaking at a word boundary and adding an
+// ellipsis if the text was truncated. If even the first word is too long, it's cut off partway
+// through instead.
+fn truncate_words(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_owned();
+    }
+
+    let mut output = String::new();
+
+    for word in text.split_whitespace() {
+        let separator = if output.is_empty() { "" } else { " " };
+
+        // Leave room for the ellipsis.
+        if output.chars().count() + separator.len() + word.chars().count() + 1 > max_chars {
+            break;
+        }
+
+        output.push_str(separator);
+        output.push_str(word);
+    }
+
+    if output.is_empty() {
+        let first_word = text.split_whitespace().next().unwrap_or_default();
+        output = first_word
+            .chars()
+            .take(max_chars.saturating_sub(1))
+            .collect();
+    }
+
+    output.push('…');
+
+    output
+}
+
+// Generate a summary of a document from its first paragraph of plain text, skipping over any
+// headings, links, lists, quotes, and preformatted blocks. This returns `None` if the document has
+// no plain text.
+pub fn summarize(body: &str, max_chars: usize) -> Option<String> {
+    let mut paragraph = Vec::new();
+
+    for line in parse(body) {
+        match line.kind {
+            LineKind::Text(text) if !text.trim().is_empty() => paragraph.push(text.trim()),
+            // A paragraph ends at the first line that isn't plain text.
+            _ if !paragraph.is_empty() => break,
+            _ => {}
+        }
+    }
+
+    if paragraph.is_empty() {
+        return None;
+    }
+
+    Some(truncate_words(&paragraph.join(" "), max_chars))
+}
+
+// Split a document at the first plain text line which consists of only `marker`. This returns the
+// text before the marker and the full document with the marker line removed, or `None` if the
+// document doesn't contain the marker.
+pub fn split_at_marker(body: &str, marker: &str) -> Option<(String, String)> {
+    let lines = parse(body);
+
+    let marker_index = lines.iter().position(
+        |line| matches!(line.kind, LineKind::Text(text) if text.trim() == marker.trim()),
+    )?;
+
+    let before = join(&lines[..marker_index]);
+
+    let without_marker = lines
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != marker_index)
+        .map(|(_, line)| *line)
+        .collect::<Vec<_>>();
+
+    Some((before, join(&without_marker)))
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// The XHTML elements which group consecutive gemtext lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum XhtmlGroup {
+    List,
+    Quote,
+}
+
+// Convert a gemtext document to XHTML. Relative links are resolved against `base_url`, since they
+// won't work outside of the capsule. Headings are shifted down a level so the document can go
+// under its own title.
+pub fn to_xhtml(body: &str, base_url: &Url) -> String {
+    let mut output = String::new();
+    let mut group = None;
+    // The lines of the preformatted block we're inside of, if any.
+    let mut preformatted: Option<Vec<String>> = None;
+
+    for line in parse(body) {
+        let line_group = match line.kind {
+            LineKind::ListItem(_) => Some(XhtmlGroup::List),
+            LineKind::Quote(_) => Some(XhtmlGroup::Quote),
+            _ => None,
+        };
+
+        if group != line_group {
+            match group {
+                Some(XhtmlGroup::List) => output.push_str("</ul>\n"),
+                Some(XhtmlGroup::Quote) => output.push_str("</blockquote>\n"),
+                None => {}
+            }
+
+            match line_group {
+                Some(XhtmlGroup::List) => output.push_str("<ul>\n"),
+                Some(XhtmlGroup::Quote) => output.push_str("<blockquote>\n"),
+                None => {}
+            }
+
+            group = line_group;
+        }
+
+        match line.kind {
+            LineKind::Text(text) if text.trim().is_empty() => {}
+            LineKind::Text(text) => output.push_str(&format!("<p>{}</p>\n", escape_xml(text))),
+            LineKind::Link { url, label } => {
+                let url = base_url
+                    .join(url)
+                    .map_or_else(|_| url.to_owned(), |url| url.to_string());
+
+                output.push_str(&format!(
+                    "<p><a href=\"{}\">{}</a></p>\n",
+                    escape_xml(&url),
+                    escape_xml(label.unwrap_or(&url))
+                ));
+            }
+            LineKind::Heading { level, text } => {
+                let level = level + 1;
+                output.push_str(&format!("<h{level}>{}</h{level}>\n", escape_xml(text)));
+            }
+            LineKind::ListItem(text) => {
+                output.push_str(&format!("<li>{}</li>\n", escape_xml(text)))
+            }
+            LineKind::Quote(text) => output.push_str(&format!("<p>{}</p>\n", escape_xml(text))),
+            LineKind::PreformatToggle { .. } => match preformatted.take() {
+                Some(lines) => output.push_str(&format!("<pre>{}</pre>\n", lines.join("\n"))),
+                None => preformatted = Some(Vec::new()),
+            },
+            LineKind::Preformatted(text) => {
+                if let Some(lines) = &mut preformatted {
+                    lines.push(escape_xml(text));
+                }
+            }
+        }
+    }
+
+    match group {
+        Some(XhtmlGroup::List) => output.push_str("</ul>\n"),
+        Some(XhtmlGroup::Quote) => output.push_str("</blockquote>\n"),
+        None => {}
+    }
+
+    // Close a preformatted block which is missing its closing toggle line.
+    if let Some(lines) = preformatted {
+        output.push_str(&format!("<pre>{}</pre>\n", lines.join("\n")));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_the_first_paragraph() {
+        let body = "# Title\n=> /link Link\n\nFirst line\nsecond line.\n\nSecond paragraph.\n";
+        assert_eq!(
+            summarize(body, 100).as_deref(),
+            Some("First line second line.")
+        );
+        assert_eq!(summarize(body, 16).as_deref(), Some("First line…"));
+        assert_eq!(summarize("# Only a heading\n```\ncode\n```\n", 100), None);
+    }
+
+    #[test]
+    fn truncates_at_word_boundaries() {
+        assert_eq!(truncate_words("one two three", 13), "one two three");
+        assert_eq!(truncate_words("one two three", 12), "one two…");
+        assert_eq!(truncate_words("one two three", 8), "one two…");
+        assert_eq!(truncate_words("one two three", 7), "one…");
+    }
+
+    #[test]
+    fn truncates_long_first_words() {
+        assert_eq!(
+            truncate_words("Pneumonoultramicroscopic words", 10),
+            "Pneumonou…"
+        );
+        assert_eq!(truncate_words("abc def", 1), "…");
+    }
+
+    #[test]
+    fn truncates_multi_byte_text() {
+        assert_eq!(truncate_words("日本語の 文章です", 7), "日本語の…");
+        assert_eq!(truncate_words("日本語の文章です", 5), "日本語の…");
+        assert_eq!(truncate_words("çà et là", 7), "çà et…");
+    }
+
+    fn xhtml(body: &str) -> String {
+        to_xhtml(
+            body,
+            &Url::parse("gemini://example.com/posts/hello.gmi").unwrap(),
+        )
+    }
+
+    #[test]
+    fn groups_list_items_and_quotes_in_xhtml() {
+        assert_eq!(
+            xhtml("* One\n* Two\n> Quoted\n> More\nText\n* Three\n"),
+            "<ul>\n<li>One</li>\n<li>Two</li>\n</ul>\n<blockquote>\n<p>Quoted</p>\n<p>More</p>\n</blockquote>\n<p>Text</p>\n<ul>\n<li>Three</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn escapes_xhtml() {
+        assert_eq!(
+            xhtml("# A & B\n<i>Text</i>\n```alt\nif a < b && c > \"d\" {}\n```\n"),
+            "<h2>A &amp; B</h2>\n<p>&lt;i&gt;Text&lt;/i&gt;</p>\n<pre>if a &lt; b &amp;&amp; c &gt; &quot;d&quot; {}</pre>\n"
+        );
+    }
+
+    #[test]
+    fn resolves_relative_links_in_xhtml() {
+        assert_eq!(
+            xhtml("=> other.gmi Other\n=> /about.gmi\n=> https://example.org/?a=1&b=2 Web\n"),
+            "<p><a href=\"gemini://example.com/posts/other.gmi\">Other</a></p>\n<p><a href=\"gemini://example.com/about.gmi\">gemini://example.com/about.gmi</a></p>\n<p><a href=\"https://example.org/?a=1&amp;b=2\">Web</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn closes_unclosed_preformatted_blocks_in_xhtml() {
+        assert_eq!(
+            xhtml("* Item\n```\n# Not a heading\n* Not an item\n"),
+            "<ul>\n<li>Item</li>\n</ul>\n<pre># Not a heading\n* Not an item</pre>\n"
+        );
+    }
+
+    fn rewrite_example(url: &str) -> Option<String> {
+        url.starts_with("old")
+            .then(|| url.replacen("old", "new", 1))
+    }
+
+    #[test]
+    fn rewrites_link_urls() {
+        let body = "# Links\n=> old.gmi Old\n=>\told.gmi#top old.gmi\n=> other.gmi\nold.gmi\n";
+
+        assert_eq!(
+            rewrite_links(body, rewrite_example).as_deref(),
+            Some("# Links\n=> new.gmi Old\n=>\tnew.gmi#top old.gmi\n=> other.gmi\nold.gmi\n")
+        );
+    }
+
+    #[test]
+    fn rewrites_urls_that_appear_in_the_link_prefix() {
+        let body = "=> = Equals\n";
+
+        assert_eq!(
+            rewrite_links(body, |url| (url == "=").then(|| String::from("/equals"))).as_deref(),
+            Some("=> /equals Equals\n")
+        );
+    }
+
+    #[test]
+    fn keeps_line_endings_when_rewriting_links() {
+        assert_eq!(
+            rewrite_links("Text\r\n=> old.gmi\r\n=> old.gmi", rewrite_example).as_deref(),
+            Some("Text\r\n=> new.gmi\r\n=> new.gmi")
+        );
+    }
+
+    #[test]
+    fn leaves_preformatted_links_alone() {
+        assert_eq!(
+            rewrite_links("```\n=> old.gmi\n```\n=> other.gmi\n", rewrite_example),
+            None
+        );
+    }
+}
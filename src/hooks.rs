@@ -0,0 +1,137 @@
+use std::path::Path;
+use std::process::Command;
+
+use eyre::{bail, WrapErr};
+
+use crate::config::Config;
+use crate::error::Error;
+
+#[cfg(windows)]
+pub fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+pub fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+// Run each hook command in order using the system shell, stopping at the first one that fails.
+// Hooks get environment variables describing the project so they don't need to parse the config
+// file themselves.
+pub fn run_hooks(
+    stage: &str,
+    commands: &[String],
+    config_path: &Path,
+    config: &Config,
+) -> eyre::Result<()> {
+    for command in commands {
+        let status = shell_command(command)
+            .env("GEMPOST_HOOK", stage)
+            .env("GEMPOST_CONFIG", config_path)
+            .env("GEMPOST_PUBLIC_DIR", &config.public_dir)
+            .env("GEMPOST_STATIC_DIR", &config.static_dir)
+            .env("GEMPOST_POSTS_DIR", &config.posts_dir)
+            .env("GEMPOST_PAGES_DIR", &config.pages_dir)
+            .env("GEMPOST_TEMPLATES_DIR", &config.templates_dir)
+            .env("GEMPOST_CAPSULE_URL", config.url.as_str())
+            .status()
+            .wrap_err(format!("failed running {stage} hook: {command}"))?;
+
+        if !status.success() {
+            bail!(Error::HookFailed {
+                stage: stage.to_owned(),
+                command: command.to_owned(),
+                status: status.to_string(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::tests::{read_config, temp_dir};
+
+    fn run(dir: &Path, commands: &[&str]) -> eyre::Result<()> {
+        let config = read_config(dir, "title: Example\nurl: gemini://example.com/\n").unwrap();
+        let commands = commands
+            .iter()
+            .map(|&command| command.to_owned())
+            .collect::<Vec<_>>();
+
+        run_hooks("pre-build", &commands, &dir.join("gempost.yaml"), &config)
+    }
+
+    #[test]
+    fn runs_hooks_in_order() {
+        let dir = temp_dir("hooks-order");
+        let output = dir.join("output.txt");
+        let output = output.to_string_lossy();
+
+        run(
+            &dir,
+            &[
+                &format!("echo first> \"{output}\""),
+                &format!("echo second>> \"{output}\""),
+            ],
+        )
+        .unwrap();
+
+        let output = fs::read_to_string(dir.join("output.txt")).unwrap();
+        assert_eq!(
+            output.lines().map(str::trim).collect::<Vec<_>>(),
+            ["first", "second"]
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_failing_hook() {
+        let dir = temp_dir("hooks-failure");
+        let output = dir.join("output.txt");
+
+        let err = run(
+            &dir,
+            &[
+                "exit 3",
+                &format!("echo ran> \"{}\"", output.to_string_lossy()),
+            ],
+        )
+        .unwrap_err();
+
+        match err.downcast_ref::<Error>() {
+            Some(Error::HookFailed { stage, command, .. }) => {
+                assert_eq!(stage, "pre-build");
+                assert_eq!(command, "exit 3");
+            }
+            _ => panic!("unexpected error: {err:?}"),
+        }
+
+        assert!(err.to_string().contains("`exit 3`"), "{err}");
+        assert!(!output.exists());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn passes_the_project_to_hooks() {
+        let dir = temp_dir("hooks-env");
+
+        run(
+            &dir,
+            &[
+                "test \"$GEMPOST_HOOK\" = pre-build",
+                "test \"$GEMPOST_CAPSULE_URL\" = gemini://example.com/",
+                "test -f \"$GEMPOST_CONFIG\"",
+            ],
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,548 @@
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, HeadingLevel, LinkType, Tag, TagEnd};
+
+use crate::markdown;
+
+// The contents of these elements are never shown.
+const HIDDEN_ELEMENTS: [&str; 4] = ["script", "style", "head", "template"];
+
+// These elements only separate paragraphs of text.
+const CONTAINER_ELEMENTS: [&str; 14] = [
+    "p",
+    "div",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "main",
+    "aside",
+    "nav",
+    "figure",
+    "figcaption",
+    "table",
+    "tr",
+    "dl",
+];
+
+// A piece of an HTML document.
+#[derive(Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Text(&'a str),
+    Open {
+        name: String,
+        attrs: Vec<(String, String)>,
+    },
+    Close {
+        name: String,
+    },
+}
+
+fn parse_attrs(source: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut rest = source.trim();
+
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let value = if let Some(after_equals) = rest.strip_prefix('=') {
+            let after_equals = after_equals.trim_start();
+
+            let (value, after_value) = match after_equals.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let quoted = &after_equals[1..];
+                    let end = quoted.find(quote).unwrap_or(quoted.len());
+                    (&quoted[..end], quoted.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = after_equals
+                        .find(char::is_whitespace)
+                        .unwrap_or(after_equals.len());
+                    (&after_equals[..end], &after_equals[end..])
+                }
+            };
+
+            rest = after_value.trim_start();
+            decode_entities(value)
+        } else {
+            String::new()
+        };
+
+        if !name.is_empty() {
+            attrs.push((name, value));
+        }
+    }
+
+    attrs
+}
+
+// Split an HTML document into text and tags. This is forgiving of malformed HTML, since it's only
+// used to extract the text and structure of a document.
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment
+                .find("-->")
+                .map_or("", |end| &comment[end + "-->".len()..]);
+            continue;
+        }
+
+        let is_tag = rest.starts_with('<')
+            && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!');
+
+        if !is_tag {
+            // A `<` which doesn't start a tag is part of the text.
+            let skip = usize::from(rest.starts_with('<'));
+            let end = rest[skip..]
+                .find('<')
+                .map_or(rest.len(), |index| index + skip);
+            tokens.push(Token::Text(&rest[..end]));
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[1..end];
+        rest = rest.get(end + 1..).unwrap_or_default();
+
+        // Skip doctypes and CDATA sections.
+        if tag.starts_with('!') {
+            continue;
+        }
+
+        if let Some(name) = tag.strip_prefix('/') {
+            tokens.push(Token::Close {
+                name: name.trim().to_lowercase(),
+            });
+            continue;
+        }
+
+        let tag = tag.strip_suffix('/').unwrap_or(tag);
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+
+        tokens.push(Token::Open {
+            name: tag[..name_end].to_lowercase(),
+            attrs: parse_attrs(&tag[name_end..]),
+        });
+    }
+
+    tokens
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+
+        return char::from_u32(code);
+    }
+
+    let decoded = match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "copy" => '©',
+        _ => return None,
+    };
+
+    Some(decoded)
+}
+
+// Replace character references like `&amp;` with the characters they represent. Unrecognized
+// references are left as-is.
+pub fn decode_entities(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+
+        match decoded {
+            Some((decoded, end)) => {
+                output.push(decoded);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    output.push_str(rest);
+
+    output
+}
+
+fn heading_level(name: &str) -> Option<HeadingLevel> {
+    match name {
+        "h1" => Some(HeadingLevel::H1),
+        "h2" => Some(HeadingLevel::H2),
+        "h3" => Some(HeadingLevel::H3),
+        "h4" => Some(HeadingLevel::H4),
+        "h5" => Some(HeadingLevel::H5),
+        "h6" => Some(HeadingLevel::H6),
+        _ => None,
+    }
+}
+
+fn attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(attr_name, _)| attr_name == name)
+        .map(|(_, value)| value.as_str())
+}
+
+// Turns HTML tokens into the equivalent Markdown events. HTML is much more permissive about how
+// elements nest than Markdown, so this keeps track of which blocks are open to make sure the events
+// are balanced.
+#[derive(Default)]
+struct EventBuilder {
+    events: Vec<Event<'static>>,
+    in_paragraph: bool,
+    heading: Option<HeadingLevel>,
+    // For each list we're inside of, whether it's ordered and whether we're inside of an item.
+    lists: Vec<(bool, bool)>,
+    in_pre: bool,
+    in_link: bool,
+    quote_depth: usize,
+    hidden_depth: usize,
+}
+
+impl EventBuilder {
+    fn in_text_block(&self) -> bool {
+        self.in_paragraph
+            || self.heading.is_some()
+            || self.in_pre
+            || self.lists.last().is_some_and(|(_, in_item)| *in_item)
+    }
+
+    // Text outside of any block, like in a `<div>`, gets its own paragraph.
+    fn ensure_text_block(&mut self) {
+        if !self.in_text_block() {
+            self.events.push(Event::Start(Tag::Paragraph));
+            self.in_paragraph = true;
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        if self.in_link {
+            self.events.push(Event::End(TagEnd::Link));
+            self.in_link = false;
+        }
+
+        if self.in_paragraph {
+            self.events.push(Event::End(TagEnd::Paragraph));
+            self.in_paragraph = false;
+        }
+    }
+
+    fn end_item(&mut self) {
+        self.end_paragraph();
+
+        if let Some((_, in_item)) = self.lists.last_mut() {
+            if *in_item {
+                *in_item = false;
+                self.events.push(Event::End(TagEnd::Item));
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.hidden_depth > 0 {
+            return;
+        }
+
+        let text = decode_entities(text);
+
+        if self.in_pre {
+            self.events.push(Event::Text(CowStr::from(text)));
+            return;
+        }
+
+        // Outside of preformatted text, HTML treats any run of whitespace as a single space.
+        if text.trim().is_empty() {
+            if self.in_text_block() {
+                self.events.push(Event::Text(CowStr::Borrowed(" ")));
+            }
+
+            return;
+        }
+
+        let mut collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if text.starts_with(char::is_whitespace) {
+            collapsed.insert(0, ' ');
+        }
+
+        if text.ends_with(char::is_whitespace) {
+            collapsed.push(' ');
+        }
+
+        self.ensure_text_block();
+        self.events.push(Event::Text(CowStr::from(collapsed)));
+    }
+
+    fn open(&mut self, name: &str, attrs: &[(String, String)]) {
+        if HIDDEN_ELEMENTS.contains(&name) {
+            self.hidden_depth += 1;
+            return;
+        }
+
+        if self.hidden_depth > 0 {
+            return;
+        }
+
+        if let Some(level) = heading_level(name) {
+            self.end_paragraph();
+            self.events.push(Event::Start(Tag::Heading {
+                level,
+                id: None,
+                classes: Vec::new(),
+                attrs: Vec::new(),
+            }));
+            self.heading = Some(level);
+            return;
+        }
+
+        match name {
+            "p" => {
+                self.end_paragraph();
+
+                // Paragraphs in list items are part of the item's text.
+                if self.lists.last().map_or(true, |(_, in_item)| !in_item) {
+                    self.events.push(Event::Start(Tag::Paragraph));
+                    self.in_paragraph = true;
+                }
+            }
+            name if CONTAINER_ELEMENTS.contains(&name) => self.end_paragraph(),
+            "ul" | "ol" => {
+                self.end_paragraph();
+                let ordered = name == "ol";
+                self.events
+                    .push(Event::Start(Tag::List(Some(1).filter(|_| ordered))));
+                self.lists.push((ordered, false));
+            }
+            "li" => {
+                // The end tag of a list item is optional.
+                self.end_item();
+
+                if let Some((_, in_item)) = self.lists.last_mut() {
+                    *in_item = true;
+                    self.events.push(Event::Start(Tag::Item));
+                }
+            }
+            "blockquote" => {
+                self.end_paragraph();
+                self.events.push(Event::Start(Tag::BlockQuote(None)));
+                self.quote_depth += 1;
+            }
+            "pre" => {
+                self.end_paragraph();
+                self.events
+                    .push(Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)));
+                self.in_pre = true;
+            }
+            "br" if self.in_text_block() => self.events.push(Event::HardBreak),
+            "code" if !self.in_pre => self.text("`"),
+            "a" => {
+                if let Some(href) = attr(attrs, "href") {
+                    self.ensure_text_block();
+                    self.events.push(Event::Start(Tag::Link {
+                        link_type: LinkType::Inline,
+                        dest_url: CowStr::from(href.to_owned()),
+                        title: CowStr::Borrowed(""),
+                        id: CowStr::Borrowed(""),
+                    }));
+                    self.in_link = true;
+                }
+            }
+            "img" => {
+                if let Some(src) = attr(attrs, "src") {
+                    self.ensure_text_block();
+                    self.events.push(Event::Start(Tag::Image {
+                        link_type: LinkType::Inline,
+                        dest_url: CowStr::from(src.to_owned()),
+                        title: CowStr::Borrowed(""),
+                        id: CowStr::Borrowed(""),
+                    }));
+                    self.events.push(Event::Text(CowStr::from(
+                        attr(attrs, "alt").unwrap_or_default().to_owned(),
+                    )));
+                    self.events.push(Event::End(TagEnd::Image));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn close(&mut self, name: &str) {
+        if HIDDEN_ELEMENTS.contains(&name) {
+            self.hidden_depth = self.hidden_depth.saturating_sub(1);
+            return;
+        }
+
+        if self.hidden_depth > 0 {
+            return;
+        }
+
+        if heading_level(name).is_some() {
+            if let Some(level) = self.heading.take() {
+                if self.in_link {
+                    self.events.push(Event::End(TagEnd::Link));
+                    self.in_link = false;
+                }
+
+                self.events.push(Event::End(TagEnd::Heading(level)));
+            }
+
+            return;
+        }
+
+        match name {
+            "p" => self.end_paragraph(),
+            name if CONTAINER_ELEMENTS.contains(&name) => self.end_paragraph(),
+            "li" => self.end_item(),
+            "ul" | "ol" => {
+                self.end_item();
+
+                if let Some((ordered, _)) = self.lists.pop() {
+                    self.events.push(Event::End(TagEnd::List(ordered)));
+                }
+            }
+            // Ignore the end tag of a quote which was never started.
+            "blockquote" if self.quote_depth > 0 => {
+                self.end_paragraph();
+                self.events.push(Event::End(TagEnd::BlockQuote(None)));
+                self.quote_depth -= 1;
+            }
+            "pre" if self.in_pre => {
+                self.events.push(Event::End(TagEnd::CodeBlock));
+                self.in_pre = false;
+            }
+            "code" if !self.in_pre => self.text("`"),
+            "a" if self.in_link => {
+                self.events.push(Event::End(TagEnd::Link));
+                self.in_link = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> Vec<Event<'static>> {
+        self.end_paragraph();
+
+        while !self.lists.is_empty() {
+            self.close("ul");
+        }
+
+        while self.quote_depth > 0 {
+            self.close("blockquote");
+        }
+
+        self.events
+    }
+}
+
+// Convert an HTML document to gemtext, the same way as a Markdown document.
+pub fn to_gemtext(html: &str) -> String {
+    let mut builder = EventBuilder::default();
+
+    for token in tokenize(html) {
+        match token {
+            Token::Text(text) => builder.text(text),
+            Token::Open { name, attrs } => builder.open(&name, &attrs),
+            Token::Close { name } => builder.close(&name),
+        }
+    }
+
+    markdown::events_to_gemtext(builder.finish())
+}
+
+// Get the text of an HTML fragment, without any of its markup.
+pub fn to_text(html: &str) -> String {
+    let text = tokenize(html)
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Text(text) => Some(decode_entities(text)),
+            _ => None,
+        })
+        .collect::<String>();
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_unbalanced_close_tags() {
+        let html = "<p>One</p></blockquote></li></ul></p><p>Two</p>";
+        assert_eq!(to_gemtext(html), "One\n\nTwo\n");
+    }
+
+    #[test]
+    fn closes_unclosed_blocks() {
+        let html = "<blockquote><p>Quoted<ul><li>Item";
+        assert_eq!(to_gemtext(html), "> Quoted\n> * Item\n");
+    }
+
+    #[test]
+    fn converts_nested_blockquotes() {
+        let html = "<blockquote><p>Outer</p><blockquote><p>Inner</p></blockquote></blockquote>";
+        assert_eq!(to_gemtext(html), "> Outer\n> Inner\n");
+    }
+
+    #[test]
+    fn converts_nested_lists() {
+        let html = "<ul><li>One<ol><li>Two</li><li>Three</li></ol></li><li>Four</ul>";
+        assert_eq!(to_gemtext(html), "* One\n* 1. Two\n* 2. Three\n* Four\n");
+    }
+
+    #[test]
+    fn converts_links_and_entities() {
+        let html = r#"<p>Read <a href="gemini://example.com/">this &amp; that</a>.</p>"#;
+        assert_eq!(
+            to_gemtext(html),
+            "Read this & that.\n=> gemini://example.com/ this & that\n"
+        );
+    }
+
+    #[test]
+    fn skips_hidden_elements() {
+        let html = "<head><title>Title</title></head><script>let x = 1;</script><p>Body</p>";
+        assert_eq!(to_gemtext(html), "Body\n");
+    }
+
+    #[test]
+    fn extracts_text() {
+        assert_eq!(
+            to_text("<b>Bold</b>\n and <i>it&#39;s</i>"),
+            "Bold and it's"
+        );
+    }
+}
@@ -0,0 +1,628 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use eyre::WrapErr;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::clock;
+use crate::config::{CollectionConfig, Config};
+use crate::entry::{is_valid_slug, RawAuthorMetadata, RawAuthorRef, RawEntryMetadata};
+use crate::file_ext::{MARKDOWN_FILE_EXT, METADATA_FILE_EXT, POST_FILE_EXT};
+use crate::gemtext::{self, LineKind};
+use crate::markdown;
+use crate::template::find_files;
+
+const YAML_FRONT_MATTER_DELIMITER: &str = "---";
+const TOML_FRONT_MATTER_DELIMITER: &str = "+++";
+
+// The marker Jekyll and Hugo use to end the summary of a post.
+const MORE_MARKER: &str = "<!--more-->";
+
+// Jekyll keeps unpublished posts in this directory.
+const JEKYLL_DRAFTS_DIR: &str = "_drafts";
+
+// Hugo uses these files for the list page of a section rather than for a post.
+const HUGO_SECTION_INDEX: &str = "_index";
+
+// Hugo page bundles are a directory named after the post containing this file.
+const HUGO_BUNDLE_INDEX: &str = "index";
+
+// Tags, categories, and authors can be either a list or a single string.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RawStringOrList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl RawStringOrList {
+    // Jekyll allows a list of tags to be written as a single string separated by spaces.
+    fn into_words(self) -> Vec<String> {
+        match self {
+            Self::One(value) => value.split_whitespace().map(ToOwned::to_owned).collect(),
+            Self::Many(values) => values,
+        }
+    }
+
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::One(value) => vec![value],
+            Self::Many(values) => values,
+        }
+    }
+}
+
+// The front matter keys used by Jekyll and Hugo that have an equivalent in gempost. Other keys are
+// ignored.
+#[derive(Debug, Default, Deserialize)]
+struct RawFrontMatter {
+    title: Option<String>,
+    slug: Option<String>,
+    date: Option<String>,
+    lastmod: Option<String>,
+    last_modified_at: Option<String>,
+    updated: Option<String>,
+    summary: Option<String>,
+    description: Option<String>,
+    author: Option<RawStringOrList>,
+    tags: Option<RawStringOrList>,
+    categories: Option<RawStringOrList>,
+    draft: Option<bool>,
+    published: Option<bool>,
+    lang: Option<String>,
+}
+
+// TOML has a native date type, which we need to turn back into a string so it can be parsed the
+// same way as dates in YAML front matter.
+fn toml_to_yaml(value: toml::Value) -> serde_yaml::Value {
+    match value {
+        toml::Value::String(value) => serde_yaml::Value::String(value),
+        toml::Value::Integer(value) => serde_yaml::Value::Number(value.into()),
+        toml::Value::Float(value) => serde_yaml::Value::Number(value.into()),
+        toml::Value::Boolean(value) => serde_yaml::Value::Bool(value),
+        toml::Value::Datetime(value) => serde_yaml::Value::String(value.to_string()),
+        toml::Value::Array(values) => {
+            serde_yaml::Value::Sequence(values.into_iter().map(toml_to_yaml).collect())
+        }
+        toml::Value::Table(table) => serde_yaml::Value::Mapping(
+            table
+                .into_iter()
+                .map(|(key, value)| (serde_yaml::Value::String(key), toml_to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+// Split a document into its front matter and its body. Front matter is YAML when surrounded by
+// `---` lines and TOML when surrounded by `+++` lines.
+fn split_front_matter(source: &str) -> eyre::Result<(RawFrontMatter, &str)> {
+    let mut lines = source.split_inclusive('\n');
+
+    let delimiter = match lines.next().map(str::trim_end) {
+        Some(YAML_FRONT_MATTER_DELIMITER) => YAML_FRONT_MATTER_DELIMITER,
+        Some(TOML_FRONT_MATTER_DELIMITER) => TOML_FRONT_MATTER_DELIMITER,
+        _ => return Ok((RawFrontMatter::default(), source)),
+    };
+
+    let front_matter_start = source.find('\n').map_or(source.len(), |index| index + 1);
+    let mut front_matter_end = front_matter_start;
+
+    for line in lines {
+        if line.trim_end() == delimiter {
+            let front_matter = &source[front_matter_start..front_matter_end];
+            let body = &source[front_matter_end + line.len()..];
+
+            let value = if delimiter == YAML_FRONT_MATTER_DELIMITER {
+                serde_yaml::from_str::<serde_yaml::Value>(front_matter)
+                    .wrap_err("front matter is not valid YAML")?
+            } else {
+                toml_to_yaml(toml::Value::Table(
+                    toml::from_str(front_matter).wrap_err("front matter is not valid TOML")?,
+                ))
+            };
+
+            // Empty front matter parses as null.
+            if value.is_null() {
+                return Ok((RawFrontMatter::default(), body));
+            }
+
+            let front_matter = serde_yaml::from_value(value)
+                .wrap_err("front matter has a value of the wrong type")?;
+
+            return Ok((front_matter, body));
+        }
+
+        front_matter_end += line.len();
+    }
+
+    // There's no closing delimiter, so this isn't front matter after all.
+    Ok((RawFrontMatter::default(), source))
+}
+
+// Parse the date formats used by Jekyll and Hugo. Dates without a time zone are assumed to be UTC.
+fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Some(datetime);
+    }
+
+    for format in ["%Y-%m-%d %H:%M:%S %z", "%Y-%m-%d %H:%M %z"] {
+        if let Ok(datetime) = DateTime::parse_from_str(date, format) {
+            return Some(datetime);
+        }
+    }
+
+    let naive_datetime = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+
+    Some(DateTime::<Utc>::from_naive_utc_and_offset(naive_datetime, Utc).into())
+}
+
+// Jekyll, kiln, and gssg put the publish date at the start of the filename, like
+// `2006-01-02-my-post.md`. This returns the date, if there is one, and the rest of the filename.
+fn split_date_prefix(stem: &str) -> (Option<DateTime<FixedOffset>>, &str) {
+    match (stem.get(..10), stem.get(10..)) {
+        (Some(prefix), Some(rest)) if rest.starts_with('-') && rest.len() > 1 => {
+            match parse_date(prefix) {
+                Some(date) => (Some(date), &rest[1..]),
+                None => (None, stem),
+            }
+        }
+        _ => (None, stem),
+    }
+}
+
+// Remove the first heading of a post if it's a level 1 heading, returning its text. Posts from
+// kiln and gssg use this heading as their title, and gempost templates usually add their own.
+fn take_title_heading(body: &str) -> Option<(String, String)> {
+    let lines = gemtext::parse(body);
+    let (index, line) = lines
+        .iter()
+        .enumerate()
+        .find(|(_, line)| !line.raw.trim().is_empty())?;
+
+    match line.kind {
+        LineKind::Heading { level: 1, text } => {
+            let rest = body.lines().skip(index + 1).collect::<Vec<_>>().join("\n");
+            Some((
+                text.to_owned(),
+                format!("{}\n", rest.trim_start_matches('\n')),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn format_date(date: DateTime<FixedOffset>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+// A post from another static site generator, converted to gemtext.
+struct ImportedPost {
+    slug: String,
+    body: String,
+    metadata: RawEntryMetadata,
+}
+
+fn import_post(
+    path: &Path,
+    relative_path: &str,
+    config: &Config,
+    collection: &CollectionConfig,
+    warn_handler: &impl Fn(&str),
+) -> eyre::Result<Option<ImportedPost>> {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let is_markdown = match ext.as_str() {
+        MARKDOWN_FILE_EXT | "markdown" => true,
+        POST_FILE_EXT => false,
+        _ => {
+            warn_handler(&format!(
+                "Skipping this file because it's not a Markdown or gemtext file: {}",
+                path.to_string_lossy()
+            ));
+            return Ok(None);
+        }
+    };
+
+    let mut stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if stem == HUGO_SECTION_INDEX {
+        return Ok(None);
+    }
+
+    // Use the name of the directory for posts in Hugo page bundles.
+    if stem == HUGO_BUNDLE_INDEX {
+        if let Some(dir_name) = path.parent().and_then(Path::file_name) {
+            stem = dir_name.to_string_lossy().into_owned();
+        }
+    }
+
+    let source = String::from_utf8(fs::read(path).wrap_err("failed reading post")?)
+        .wrap_err("post is not valid UTF-8")?;
+
+    let (front_matter, body) = split_front_matter(&source)?;
+
+    let mut body = if is_markdown {
+        // Keep the end of the summary as an excerpt marker, if excerpts are turned on.
+        match &config.excerpt_marker {
+            Some(marker) => {
+                markdown::to_gemtext(&body.replace(MORE_MARKER, &format!("\n\n{marker}\n\n")))
+            }
+            None => markdown::to_gemtext(body),
+        }
+    } else {
+        body.to_owned()
+    };
+
+    let (filename_date, slug) = split_date_prefix(&stem);
+    let slug = front_matter.slug.unwrap_or_else(|| slug.to_owned());
+
+    if !is_valid_slug(&slug) {
+        warn_handler(&format!(
+            "Skipping this post because its slug `{slug}` can't be used as a filename: {}",
+            path.to_string_lossy()
+        ));
+        return Ok(None);
+    }
+
+    let title = match (front_matter.title, take_title_heading(&body)) {
+        (Some(title), Some((heading, rest))) if heading == title => {
+            body = rest;
+            title
+        }
+        (Some(title), _) => title,
+        (None, Some((heading, rest))) => {
+            body = rest;
+            heading
+        }
+        (None, None) => slug.clone(),
+    };
+
+    let parse_front_matter_date = |date: Option<String>| -> Option<DateTime<FixedOffset>> {
+        let date = date?;
+        let parsed = parse_date(&date);
+
+        if parsed.is_none() {
+            warn_handler(&format!(
+                "Ignoring the date `{date}` because it's not in a format gempost understands: {}",
+                path.to_string_lossy()
+            ));
+        }
+
+        parsed
+    };
+
+    let published = parse_front_matter_date(front_matter.date).or(filename_date);
+    let updated = parse_front_matter_date(
+        front_matter
+            .lastmod
+            .or(front_matter.last_modified_at)
+            .or(front_matter.updated),
+    );
+
+    let updated = match updated.or(published) {
+        Some(updated) => updated,
+        None => {
+            warn_handler(&format!(
+                "Using the current time as the last updated time because this post has no date: {}",
+                path.to_string_lossy()
+            ));
+            clock::now()?
+        }
+    };
+
+    let mut categories = Vec::new();
+
+    for category in front_matter
+        .tags
+        .into_iter()
+        .chain(front_matter.categories)
+        .flat_map(RawStringOrList::into_words)
+    {
+        if !categories.contains(&category) {
+            categories.push(category);
+        }
+    }
+
+    // Authors which match a handle in the config file refer to it. Otherwise, they're written
+    // out inline.
+    let mut authors = front_matter
+        .author
+        .map(RawStringOrList::into_vec)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|author| {
+            if config.authors.contains_key(&author) {
+                RawAuthorRef::Handle(author)
+            } else {
+                RawAuthorRef::Inline(RawAuthorMetadata {
+                    name: author,
+                    email: None,
+                    uri: None,
+                })
+            }
+        });
+
+    let author = authors.next();
+    let contributors = authors.collect::<Vec<_>>();
+
+    let is_draft = front_matter.draft.unwrap_or(false)
+        || front_matter.published == Some(false)
+        || relative_path
+            .split('/')
+            .any(|segment| segment == JEKYLL_DRAFTS_DIR);
+
+    // Derive the ID from the URL of the feed and the slug so that importing the same post again
+    // gives it the same ID.
+    let feed_url = config
+        .url
+        .join(&collection.feed_path)
+        .wrap_err("failed building the feed URL")?;
+    let id = Uuid::new_v5(
+        &Uuid::NAMESPACE_URL,
+        format!("{feed_url}#{slug}").as_bytes(),
+    );
+
+    Ok(Some(ImportedPost {
+        slug,
+        body,
+        metadata: RawEntryMetadata {
+            id: format!("urn:uuid:{id}"),
+            title,
+            updated: format_date(updated),
+            summary: front_matter.summary.or(front_matter.description),
+            published: published.map(format_date),
+            author,
+            contributors: Some(contributors).filter(|contributors| !contributors.is_empty()),
+            lang: front_matter.lang,
+            categories: Some(categories).filter(|categories| !categories.is_empty()),
+            draft: Some(true).filter(|_| is_draft),
+            ..Default::default()
+        },
+    }))
+}
+
+// Create a new file with the given contents. This returns `false` without writing anything if the
+// file already exists.
+fn create_new_file(path: &Path, contents: &str) -> eyre::Result<bool> {
+    let mut file = match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => return Ok(false),
+        Err(err) => Err(err).wrap_err("failed creating imported post file")?,
+    };
+
+    if let Err(err) = file.write_all(contents.as_bytes()) {
+        drop(file);
+        fs::remove_file(path).wrap_err("failed removing partially written post file")?;
+        return Err(err).wrap_err("failed writing imported post file");
+    }
+
+    Ok(true)
+}
+
+// Write a post and its metadata file to the posts directory. This returns `false` without writing
+// anything if there's already a post with the same slug.
+pub fn write_post(
+    posts_dir: &Path,
+    slug: &str,
+    body: &str,
+    metadata: &RawEntryMetadata,
+) -> eyre::Result<bool> {
+    let existing_paths = [POST_FILE_EXT, MARKDOWN_FILE_EXT, METADATA_FILE_EXT]
+        .map(|ext| posts_dir.join(format!("{slug}.{ext}")));
+
+    if existing_paths.iter().any(|path| path.exists()) {
+        return Ok(false);
+    }
+
+    let metadata_yaml =
+        serde_yaml::to_string(metadata).wrap_err("failed serializing post metadata")?;
+
+    let post_path = posts_dir.join(format!("{slug}.{POST_FILE_EXT}"));
+    let metadata_path = posts_dir.join(format!("{slug}.{METADATA_FILE_EXT}"));
+
+    if !create_new_file(&post_path, body)? {
+        return Ok(false);
+    }
+
+    match create_new_file(&metadata_path, &metadata_yaml) {
+        Ok(true) => Ok(true),
+        // Don't leave a post behind without its metadata file, since it would fail the next build.
+        result => {
+            fs::remove_file(&post_path).wrap_err("failed removing imported post file")?;
+            result
+        }
+    }
+}
+
+// Import the Markdown and gemtext posts in a directory from another static site generator into a
+// collection. This returns the number of posts imported.
+pub fn import_posts(
+    source_dir: &Path,
+    config: &Config,
+    collection: &CollectionConfig,
+    warn_handler: impl Fn(&str),
+) -> eyre::Result<usize> {
+    let mut source_files = Vec::new();
+    find_files(source_dir, source_dir, &mut source_files)
+        .wrap_err("failed finding posts to import")?;
+
+    fs::create_dir_all(&collection.posts_dir).wrap_err("failed creating posts directory")?;
+
+    let mut imported = BTreeMap::new();
+
+    for (path, relative_path) in source_files {
+        let post = match import_post(&path, &relative_path, config, collection, &warn_handler)
+            .wrap_err(format!("failed importing post: {}", path.to_string_lossy()))?
+        {
+            Some(post) => post,
+            None => continue,
+        };
+
+        if let Some(other_path) = imported.get(&post.slug) {
+            warn_handler(&format!(
+                "Skipping this post because it has the same slug as {other_path}: {}",
+                path.to_string_lossy()
+            ));
+            continue;
+        }
+
+        if !write_post(
+            &collection.posts_dir,
+            &post.slug,
+            &post.body,
+            &post.metadata,
+        )? {
+            warn_handler(&format!(
+                "Skipping this post because there's already a post with the slug `{}`: {}",
+                post.slug,
+                path.to_string_lossy()
+            ));
+            continue;
+        }
+
+        imported.insert(post.slug, relative_path);
+    }
+
+    Ok(imported.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::temp_dir;
+
+    #[test]
+    fn splits_yaml_front_matter() {
+        let source = "---\n# A comment\ntitle: \"Hello\" # Another comment\ntags: [one, two]\nsummary: >\n  Folded\n  text\n---\nBody\n";
+        let (front_matter, body) = split_front_matter(source).unwrap();
+
+        assert_eq!(front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(
+            front_matter.tags.map(RawStringOrList::into_words),
+            Some(vec![String::from("one"), String::from("two")])
+        );
+        assert_eq!(front_matter.summary.as_deref(), Some("Folded text\n"));
+        assert_eq!(body, "Body\n");
+    }
+
+    #[test]
+    fn splits_front_matter_with_crlf_line_endings() {
+        let source = "---\r\ntitle: Hello\r\ntags: one two\r\n---\r\nBody\r\n";
+        let (front_matter, body) = split_front_matter(source).unwrap();
+
+        assert_eq!(front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(
+            front_matter.tags.map(RawStringOrList::into_words),
+            Some(vec![String::from("one"), String::from("two")])
+        );
+        assert_eq!(body, "Body\r\n");
+    }
+
+    #[test]
+    fn splits_toml_front_matter() {
+        let source = "+++\ntitle = \"Hello\"\ndate = 2006-01-02T15:04:05Z\n+++\nBody\n";
+        let (front_matter, body) = split_front_matter(source).unwrap();
+
+        assert_eq!(front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(
+            front_matter.date.as_deref().and_then(parse_date),
+            DateTime::parse_from_rfc3339("2006-01-02T15:04:05Z").ok()
+        );
+        assert_eq!(body, "Body\n");
+    }
+
+    #[test]
+    fn leaves_documents_without_front_matter_alone() {
+        for source in ["Body\n", "---\ntitle: Unclosed\n", "--- not front matter\n"] {
+            let (front_matter, body) = split_front_matter(source).unwrap();
+
+            assert!(front_matter.title.is_none());
+            assert_eq!(body, source);
+        }
+
+        let (front_matter, body) = split_front_matter("---\n---\nBody\n").unwrap();
+        assert!(front_matter.title.is_none());
+        assert_eq!(body, "Body\n");
+    }
+
+    #[test]
+    fn rejects_invalid_front_matter() {
+        assert!(split_front_matter("---\ntitle: [unclosed\n---\n").is_err());
+        assert!(split_front_matter("---\ndraft: maybe\n---\n").is_err());
+    }
+
+    #[test]
+    fn parses_dates() {
+        let expected = DateTime::parse_from_rfc3339("2006-01-02T15:04:05-07:00").unwrap();
+
+        assert_eq!(parse_date("2006-01-02T15:04:05-07:00"), Some(expected));
+        assert_eq!(parse_date("2006-01-02 15:04:05 -0700"), Some(expected));
+        assert_eq!(
+            parse_date("2006-01-02"),
+            DateTime::parse_from_rfc3339("2006-01-02T00:00:00Z").ok()
+        );
+        assert_eq!(parse_date("January 2, 2006"), None);
+    }
+
+    #[test]
+    fn splits_date_prefixes() {
+        let (date, slug) = split_date_prefix("2006-01-02-my-post");
+        assert_eq!(
+            date,
+            DateTime::parse_from_rfc3339("2006-01-02T00:00:00Z").ok()
+        );
+        assert_eq!(slug, "my-post");
+
+        assert_eq!(split_date_prefix("2006-01-02"), (None, "2006-01-02"));
+        assert_eq!(split_date_prefix("my-post"), (None, "my-post"));
+    }
+
+    #[test]
+    fn takes_title_headings() {
+        assert_eq!(
+            take_title_heading("\n# Title\n\nBody\n"),
+            Some((String::from("Title"), String::from("Body\n")))
+        );
+        assert_eq!(take_title_heading("## Subheading\n\nBody\n"), None);
+        assert_eq!(take_title_heading("Body\n# Title\n"), None);
+    }
+
+    #[test]
+    fn writes_posts_with_new_slugs() {
+        let dir = temp_dir("import-write-post");
+        let metadata = RawEntryMetadata {
+            id: String::from("urn:uuid:1"),
+            title: String::from("Title"),
+            updated: String::from("2024-01-01T00:00:00Z"),
+            ..Default::default()
+        };
+
+        assert!(write_post(&dir, "post", "Body\n", &metadata).unwrap());
+        assert_eq!(fs::read_to_string(dir.join("post.gmi")).unwrap(), "Body\n");
+        assert!(fs::read_to_string(dir.join("post.yaml"))
+            .unwrap()
+            .contains("title: Title"));
+
+        assert!(!write_post(&dir, "post", "New body\n", &metadata).unwrap());
+        assert_eq!(fs::read_to_string(dir.join("post.gmi")).unwrap(), "Body\n");
+    }
+}
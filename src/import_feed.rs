@@ -0,0 +1,505 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::DateTime;
+use eyre::{bail, WrapErr};
+use roxmltree::{Document, Node, NS_XML_URI};
+
+use crate::config::{CollectionConfig, Config};
+use crate::entry::{RawAuthorMetadata, RawAuthorRef, RawEntryMetadata};
+use crate::error::Error;
+use crate::gemtext;
+use crate::html;
+use crate::import::write_post;
+
+const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+
+// Gemini feeds sometimes include the gemtext of each post as-is.
+const GEMTEXT_MIME_TYPE: &str = "text/gemini";
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.has_tag_name((ATOM_NS, name)))
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.has_tag_name((ATOM_NS, name)))
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_owned())
+}
+
+// Turn the XHTML inside of an element back into markup so it can be converted like HTML content.
+fn xhtml_to_html(node: Node) -> String {
+    let mut output = String::new();
+
+    for child in node.children() {
+        if child.is_text() {
+            let text = child.text().unwrap_or_default();
+            output.push_str(
+                &text
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;"),
+            );
+        } else if child.is_element() {
+            let name = child.tag_name().name();
+            output.push('<');
+            output.push_str(name);
+
+            for attr in child.attributes() {
+                output.push_str(&format!(
+                    " {}=\"{}\"",
+                    attr.name(),
+                    attr.value().replace('&', "&amp;").replace('"', "&quot;")
+                ));
+            }
+
+            output.push('>');
+            output.push_str(&xhtml_to_html(child));
+            output.push_str(&format!("</{name}>"));
+        }
+    }
+
+    output
+}
+
+// The markup of an Atom text construct, like a title or summary, and whether it's HTML.
+fn text_construct_html(node: Node) -> (String, bool) {
+    match node.attribute("type").unwrap_or("text") {
+        "html" => (node.text().unwrap_or_default().to_owned(), true),
+        // XHTML content is wrapped in a `<div>`, which isn't part of the content.
+        "xhtml" => {
+            let div = node.children().find(|child| child.is_element());
+            (div.map(xhtml_to_html).unwrap_or_default(), true)
+        }
+        _ => (node.text().unwrap_or_default().to_owned(), false),
+    }
+}
+
+// Get the text of an Atom text construct without any markup.
+fn text_construct(node: Node) -> String {
+    match text_construct_html(node) {
+        (markup, true) => html::to_text(&markup),
+        (text, false) => text.trim().to_owned(),
+    }
+}
+
+fn content_to_gemtext(content: Node) -> Option<String> {
+    if let Some(src) = content.attribute("src") {
+        return Some(format!("=> {src}\n"));
+    }
+
+    match content.attribute("type") {
+        Some(mime_type) if mime_type.starts_with(GEMTEXT_MIME_TYPE) => {
+            Some(content.text().unwrap_or_default().to_owned())
+        }
+        Some(mime_type) if mime_type.contains('/') && !mime_type.starts_with("text/") => None,
+        _ => match text_construct_html(content) {
+            (markup, true) => Some(html::to_gemtext(&markup)),
+            (text, false) => {
+                let text = gemtext::escape(text.trim());
+                Some(if text.is_empty() { text } else { text + "\n" })
+            }
+        },
+    }
+}
+
+// Make a URL slug out of a post title.
+fn slugify(title: &str) -> String {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// Use the last segment of the post's URL as its slug, falling back to its title.
+fn entry_slug(entry: Node, title: &str) -> String {
+    let url = children(entry, "link")
+        .find(|link| link.attribute("rel").unwrap_or("alternate") == "alternate")
+        .and_then(|link| link.attribute("href"));
+
+    let segments = url
+        .unwrap_or_default()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+
+    // Skip over the host of absolute URLs.
+    let segments = match segments.first() {
+        Some(scheme) if scheme.ends_with(':') => &segments[segments.len().min(2)..],
+        _ => &segments[..],
+    };
+
+    let stem = |segment: &str| {
+        Path::new(segment)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+
+    let slug = match segments {
+        [.., parent, last] if stem(last) == "index" => stem(parent),
+        [.., last] => stem(last),
+        [] => String::new(),
+    };
+
+    let slug = slugify(&slug);
+
+    if slug.is_empty() {
+        slugify(title)
+    } else {
+        slug
+    }
+}
+
+// Refer to authors in the config file by their handle when the name matches, and write them out
+// inline otherwise.
+fn author_ref(person: Node, config: &Config) -> Option<RawAuthorRef> {
+    let name = child_text(person, "name")?;
+
+    let handle = config
+        .authors
+        .iter()
+        .find(|(_, author)| author.name == name)
+        .map(|(handle, _)| handle.clone());
+
+    Some(match handle {
+        Some(handle) => RawAuthorRef::Handle(handle),
+        None => RawAuthorRef::Inline(RawAuthorMetadata {
+            name,
+            email: child_text(person, "email"),
+            uri: child_text(person, "uri"),
+        }),
+    })
+}
+
+fn import_entry(
+    entry: Node,
+    config: &Config,
+    warn_handler: &impl Fn(&str),
+) -> Option<(String, String, RawEntryMetadata)> {
+    let id = match child_text(entry, "id") {
+        Some(id) if !id.is_empty() => id,
+        _ => {
+            warn_handler("Skipping an entry because it has no ID.");
+            return None;
+        }
+    };
+
+    let title = child(entry, "title")
+        .map(text_construct)
+        .unwrap_or_default();
+
+    let is_rfc3339 = |date: &String| DateTime::parse_from_rfc3339(date).is_ok();
+
+    let updated = match child_text(entry, "updated").filter(is_rfc3339) {
+        Some(updated) => updated,
+        None => {
+            warn_handler(&format!(
+                "Skipping this entry because it has no valid `updated` time: {id}"
+            ));
+            return None;
+        }
+    };
+
+    let body = match child(entry, "content") {
+        Some(content) => match content_to_gemtext(content) {
+            Some(body) => body,
+            None => {
+                warn_handler(&format!(
+                    "Leaving out the content of this entry because it's not text: {id}"
+                ));
+                String::new()
+            }
+        },
+        None => String::new(),
+    };
+
+    let mut authors = children(entry, "author").filter_map(|author| author_ref(author, config));
+    let author = authors.next();
+
+    let contributors = authors
+        .chain(children(entry, "contributor").filter_map(|person| author_ref(person, config)))
+        .collect::<Vec<_>>();
+
+    let mut categories = Vec::new();
+
+    // The label is the human-readable name of the category, while the term is often a slug, like
+    // in the feeds gempost generates.
+    let category_names = children(entry, "category").filter_map(|category| {
+        category
+            .attribute("label")
+            .filter(|label| !label.trim().is_empty())
+            .or_else(|| category.attribute("term"))
+    });
+
+    for name in category_names {
+        if !categories.iter().any(|category| category == name) {
+            categories.push(name.to_owned());
+        }
+    }
+
+    // The language can be set on the entry or inherited from the feed.
+    let lang = entry
+        .ancestors()
+        .find_map(|node| node.attribute((NS_XML_URI, "lang")))
+        .map(ToOwned::to_owned);
+
+    let slug = entry_slug(entry, &title);
+
+    let metadata = RawEntryMetadata {
+        id,
+        title,
+        updated,
+        summary: child(entry, "summary").map(text_construct),
+        published: child_text(entry, "published").filter(is_rfc3339),
+        author,
+        contributors: Some(contributors).filter(|contributors| !contributors.is_empty()),
+        rights: child(entry, "rights").map(text_construct),
+        lang,
+        categories: Some(categories).filter(|categories| !categories.is_empty()),
+        ..Default::default()
+    };
+
+    Some((slug, body, metadata))
+}
+
+// Create a post for each entry in an Atom feed, keeping their original IDs. This returns the number
+// of posts imported.
+pub fn import_feed(
+    feed_path: &Path,
+    config: &Config,
+    collection: &CollectionConfig,
+    warn_handler: impl Fn(&str),
+) -> eyre::Result<usize> {
+    let source = fs::read_to_string(feed_path).wrap_err("failed reading feed file")?;
+
+    let document = match Document::parse(&source) {
+        Ok(document) => document,
+        Err(err) => bail!(Error::InvalidFeedFile {
+            path: feed_path.to_owned(),
+            reason: err.to_string(),
+        }),
+    };
+
+    let feed = document.root_element();
+
+    if !feed.has_tag_name((ATOM_NS, "feed")) {
+        bail!(Error::InvalidFeedFile {
+            path: feed_path.to_owned(),
+            reason: String::from("This is not an Atom feed."),
+        });
+    }
+
+    fs::create_dir_all(&collection.posts_dir).wrap_err("failed creating posts directory")?;
+
+    let mut count = 0;
+
+    for entry in children(feed, "entry") {
+        let (slug, body, metadata) = match import_entry(entry, config, &warn_handler) {
+            Some(post) => post,
+            None => continue,
+        };
+
+        if slug.is_empty() {
+            warn_handler(&format!(
+                "Skipping this entry because it has no title or URL to make a slug from: {}",
+                metadata.id
+            ));
+            continue;
+        }
+
+        if !write_post(&collection.posts_dir, &slug, &body, &metadata)? {
+            warn_handler(&format!(
+                "Skipping this entry because there's already a post with the slug `{slug}`: {}",
+                metadata.id
+            ));
+            continue;
+        }
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::{read_config, temp_dir};
+
+    const CONFIG: &str =
+        "title: Example\nurl: gemini://example.com/\nauthors:\n  jane:\n    name: Jane Doe\n";
+
+    fn feed(entry: &str) -> String {
+        format!("<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"en\"><entry><id>urn:uuid:1</id><title>Hello</title><updated>2024-01-02T00:00:00Z</updated>{entry}</entry></feed>")
+    }
+
+    fn import(name: &str, entry: &str) -> Option<(String, String, RawEntryMetadata)> {
+        let config = read_config(&temp_dir(name), CONFIG).unwrap();
+        let source = feed(entry);
+        let document = Document::parse(&source).unwrap();
+        let entry = children(document.root_element(), "entry").next().unwrap();
+
+        import_entry(entry, &config, &|_| {})
+    }
+
+    #[test]
+    fn converts_html_content() {
+        let (_, body, _) = import(
+            "import-feed-html",
+            "<content type=\"html\">&lt;p&gt;Read &lt;a href=\"/a.gmi\"&gt;this&lt;/a&gt;.&lt;/p&gt;&lt;h2&gt;Next&lt;/h2&gt;</content>",
+        )
+        .unwrap();
+
+        assert_eq!(body, "Read this.\n=> /a.gmi this\n\n## Next\n");
+    }
+
+    #[test]
+    fn escapes_text_content() {
+        let (_, body, _) = import(
+            "import-feed-text",
+            "<content type=\"text\">\n# Not a heading &lt;p&gt;\n</content>",
+        )
+        .unwrap();
+
+        assert_eq!(body, " # Not a heading <p>\n");
+
+        let (_, body, _) = import(
+            "import-feed-default-text",
+            "<content>=&gt; not a link</content>",
+        )
+        .unwrap();
+
+        assert_eq!(body, " => not a link\n");
+    }
+
+    #[test]
+    fn keeps_gemtext_content() {
+        let (_, body, _) = import(
+            "import-feed-gemtext",
+            "<content type=\"text/gemini\"># Heading\n=&gt; /a.gmi Link\n</content>",
+        )
+        .unwrap();
+
+        assert_eq!(body, "# Heading\n=> /a.gmi Link\n");
+    }
+
+    #[test]
+    fn prefers_category_labels_over_terms() {
+        let (_, _, metadata) = import(
+            "import-feed-categories",
+            "<category term=\"rust-lang\" label=\"Rust\"/><category term=\"gemini\"/><category term=\"blank\" label=\" \"/><category term=\"Rust\"/>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata.categories,
+            Some(vec![
+                String::from("Rust"),
+                String::from("gemini"),
+                String::from("blank"),
+            ])
+        );
+    }
+
+    #[test]
+    fn leaves_out_missing_or_invalid_publish_times() {
+        let (_, _, metadata) = import("import-feed-no-published", "").unwrap();
+        assert_eq!(metadata.published, None);
+
+        let (_, _, metadata) = import(
+            "import-feed-invalid-published",
+            "<published>yesterday</published>",
+        )
+        .unwrap();
+        assert_eq!(metadata.published, None);
+
+        let (_, _, metadata) = import(
+            "import-feed-published",
+            "<published>2024-01-01T00:00:00Z</published>",
+        )
+        .unwrap();
+        assert_eq!(metadata.published.as_deref(), Some("2024-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn refers_to_known_authors_by_handle() {
+        let (_, _, metadata) = import(
+            "import-feed-authors",
+            "<author><name>Jane Doe</name></author><contributor><name>Alex Smith</name><email>alex@example.com</email></contributor>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata.author,
+            Some(RawAuthorRef::Handle(String::from("jane")))
+        );
+        assert_eq!(
+            metadata.contributors,
+            Some(vec![RawAuthorRef::Inline(RawAuthorMetadata {
+                name: String::from("Alex Smith"),
+                email: Some(String::from("alex@example.com")),
+                uri: None,
+            })])
+        );
+        assert_eq!(metadata.lang.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn skips_entries_without_an_updated_time() {
+        let config = read_config(&temp_dir("import-feed-no-updated"), CONFIG).unwrap();
+        let source =
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\"><entry><id>urn:uuid:1</id></entry></feed>";
+        let document = Document::parse(source).unwrap();
+        let entry = children(document.root_element(), "entry").next().unwrap();
+
+        assert_eq!(import_entry(entry, &config, &|_| {}), None);
+    }
+
+    #[test]
+    fn keeps_entry_ids() {
+        let dir = temp_dir("import-feed-ids");
+        let posts_dir = dir.join("posts");
+        let config = read_config(
+            &dir,
+            &format!("{CONFIG}posts_dir: \"{}\"\n", posts_dir.to_string_lossy()),
+        )
+        .unwrap();
+
+        let feed_path = dir.join("atom.xml");
+        fs::write(
+            &feed_path,
+            feed("<link href=\"gemini://example.com/posts/hello-world.gmi\"/><content>Hello</content>"),
+        )
+        .unwrap();
+
+        let count = import_feed(&feed_path, &config, &config.main_collection(), |_| {}).unwrap();
+        assert_eq!(count, 1);
+
+        let metadata = RawEntryMetadata::read(&posts_dir.join("hello-world.yaml")).unwrap();
+        assert_eq!(metadata.id, "urn:uuid:1");
+        assert_eq!(metadata.updated, "2024-01-02T00:00:00Z");
+        assert_eq!(
+            fs::read_to_string(posts_dir.join("hello-world.gmi")).unwrap(),
+            "Hello\n"
+        );
+
+        // Importing the same feed again skips the posts that already exist.
+        let count = import_feed(&feed_path, &config, &config.main_collection(), |_| {}).unwrap();
+        assert_eq!(count, 0);
+    }
+}
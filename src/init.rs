@@ -2,11 +2,12 @@ use std::fs::{self, OpenOptions};
 use std::io::{self, Write};
 use std::path::Path;
 
-use chrono::{Local, SecondsFormat};
+use chrono::SecondsFormat;
 use eyre::{bail, WrapErr};
 use tera::{Context, Tera};
 use uuid::Uuid;
 
+use crate::clock;
 use crate::error::Error;
 
 // We need to use conditional compilation here because `include_str` interprets the path in a
@@ -87,7 +88,7 @@ fn generate_example_metadata_file(template: &str) -> eyre::Result<String> {
     context.insert("id", &format!("urn:uuid:{}", Uuid::new_v4()));
     context.insert(
         "timestamp",
-        &Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+        &clock::now()?.to_rfc3339_opts(SecondsFormat::Secs, false),
     );
 
     tera.render("metadata", &context)
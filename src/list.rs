@@ -0,0 +1,185 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, SecondsFormat};
+use eyre::WrapErr;
+use serde::Serialize;
+
+use crate::clock;
+use crate::entry::Entry;
+use crate::feed::Feed;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum PostStatus {
+    Published,
+    Draft,
+    // Published posts with a publish time in the future.
+    Scheduled,
+}
+
+impl PostStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Published => "published",
+            Self::Draft => "draft",
+            Self::Scheduled => "scheduled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ListedPost {
+    slug: String,
+    title: String,
+    status: PostStatus,
+    published: Option<String>,
+    updated: String,
+    categories: Vec<String>,
+    url: String,
+}
+
+impl ListedPost {
+    fn new(entry: Entry, now: DateTime<FixedOffset>) -> Self {
+        let status = if entry.metadata.draft {
+            PostStatus::Draft
+        } else if entry
+            .metadata
+            .published
+            .is_some_and(|published| published > now)
+        {
+            PostStatus::Scheduled
+        } else {
+            PostStatus::Published
+        };
+
+        let format_date =
+            |date: DateTime<FixedOffset>| date.to_rfc3339_opts(SecondsFormat::Secs, false);
+
+        Self {
+            slug: entry.slug,
+            title: entry.metadata.title,
+            status,
+            published: entry.metadata.published.map(format_date),
+            updated: format_date(entry.metadata.updated),
+            categories: entry.metadata.categories,
+            url: entry.url.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    pub category: Option<String>,
+    pub drafts: bool,
+    pub since: Option<NaiveDate>,
+}
+
+impl ListFilter {
+    fn matches(&self, entry: &Entry) -> bool {
+        if let Some(category) = &self.category {
+            if !entry.metadata.categories.contains(category) {
+                return false;
+            }
+        }
+
+        if self.drafts && !entry.metadata.draft {
+            return false;
+        }
+
+        if let Some(since) = self.since {
+            if entry.date().date_naive() < since {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+// Only the date is shown in the table to keep it narrow.
+fn table_date(date: &str) -> &str {
+    date.get(..10).unwrap_or(date)
+}
+
+fn format_table(posts: &[ListedPost]) -> String {
+    let header = [
+        "STATUS",
+        "PUBLISHED",
+        "UPDATED",
+        "SLUG",
+        "TITLE",
+        "CATEGORIES",
+        "URL",
+    ]
+    .map(String::from);
+
+    let rows = posts
+        .iter()
+        .map(|post| {
+            [
+                post.status.as_str().to_owned(),
+                post.published
+                    .as_deref()
+                    .map(table_date)
+                    .unwrap_or("-")
+                    .to_owned(),
+                table_date(&post.updated).to_owned(),
+                post.slug.clone(),
+                post.title.clone(),
+                post.categories.join(", "),
+                post.url.clone(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = [0; 7];
+
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut output = String::new();
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+
+    output
+}
+
+// List the posts in a feed, including drafts, newest first. This returns either a table or, if
+// `json` is set, a JSON array.
+pub fn list_posts(feed: Feed, filter: &ListFilter, json: bool) -> eyre::Result<String> {
+    let now = clock::now()?;
+
+    let mut entries = feed
+        .entries
+        .into_iter()
+        .chain(feed.drafts)
+        .filter(|entry| filter.matches(entry))
+        .collect::<Vec<_>>();
+
+    entries.sort_by(|a, b| b.date().cmp(&a.date()).then_with(|| a.slug.cmp(&b.slug)));
+
+    let posts = entries
+        .into_iter()
+        .map(|entry| ListedPost::new(entry, now))
+        .collect::<Vec<_>>();
+
+    if json {
+        let mut output = serde_json::to_string_pretty(&posts)
+            .wrap_err("failed serializing the list of posts")?;
+        output.push('\n');
+        return Ok(output);
+    }
+
+    Ok(format_table(&posts))
+}
@@ -1,12 +1,30 @@
 mod build;
 mod cli;
+mod clock;
 mod config;
 mod entry;
+mod epub;
 mod error;
 mod feed;
+mod file_ext;
+mod filters;
+mod gemtext;
+mod hooks;
+mod html;
+mod import;
+mod import_feed;
 mod init;
+mod list;
+mod markdown;
 mod new;
+mod processor;
+mod publish;
+mod redirects;
+mod rename;
+mod robots;
+mod sidecar;
 mod template;
+mod touch;
 
 use std::path::Path;
 use std::process::ExitCode;
@@ -18,8 +36,17 @@ use new::create_new_post;
 use crate::build::build_capsule;
 use crate::cli::Cli;
 use crate::config::Config;
+use crate::epub::export_epub;
 use crate::error::Error;
+use crate::feed::Feed;
+use crate::hooks::run_hooks;
+use crate::import::import_posts;
+use crate::import_feed::import_feed;
 use crate::init::init_project;
+use crate::list::{list_posts, ListFilter};
+use crate::publish::publish_post;
+use crate::rename::rename_post;
+use crate::touch::touch_post;
 
 fn run() -> eyre::Result<()> {
     let args = Cli::parse();
@@ -35,15 +62,127 @@ fn run() -> eyre::Result<()> {
             let config =
                 Config::read(&build.config).wrap_err("failed reading the gempost config file")?;
 
+            run_hooks("pre-build", &config.hooks.pre_build, &build.config, &config)
+                .wrap_err("failed running pre-build hooks")?;
+
             build_capsule(&config).wrap_err("failed building the capsule")?;
+
+            run_hooks(
+                "post-build",
+                &config.hooks.post_build,
+                &build.config,
+                &config,
+            )
+            .wrap_err("failed running post-build hooks")?;
         }
         cli::Commands::New(new) => {
             let config =
                 Config::read(&new.config).wrap_err("failed reading the gempost config file")?;
 
-            create_new_post(&config.posts_dir, &new.slug, new.title.as_deref())
+            let collection = config.collection_or_main(new.collection.as_deref())?;
+
+            create_new_post(&collection.posts_dir, &new.slug, new.title.as_deref())
                 .wrap_err("failed creating new gemlog post")?;
         }
+        cli::Commands::Import(import) => {
+            let config =
+                Config::read(&import.config).wrap_err("failed reading the gempost config file")?;
+
+            let collection = config.collection_or_main(import.collection.as_deref())?;
+
+            let warn_handler = |msg: &str| eprintln!("Warning: {}", msg);
+
+            let count = import_posts(&import.source_dir, &config, &collection, warn_handler)
+                .wrap_err("failed importing posts")?;
+
+            println!(
+                "Imported {count} posts into `{}`.",
+                collection.posts_dir.to_string_lossy()
+            );
+        }
+        cli::Commands::Export(export) => {
+            let config =
+                Config::read(&export.config).wrap_err("failed reading the gempost config file")?;
+
+            let collection = config.collection_or_main(export.collection.as_deref())?;
+
+            let warn_handler = |msg: &str| eprintln!("Warning: {}", msg);
+
+            let feed = Feed::from_config(&config, &collection, warn_handler)
+                .wrap_err("failed reading the gemlog posts")?;
+
+            export_epub(feed, &export.epub).wrap_err("failed exporting the EPUB")?;
+        }
+        cli::Commands::ImportFeed(import) => {
+            let config =
+                Config::read(&import.config).wrap_err("failed reading the gempost config file")?;
+
+            let collection = config.collection_or_main(import.collection.as_deref())?;
+
+            let warn_handler = |msg: &str| eprintln!("Warning: {}", msg);
+
+            let count = import_feed(&import.feed_file, &config, &collection, warn_handler)
+                .wrap_err("failed importing posts from feed")?;
+
+            println!(
+                "Imported {count} posts into `{}`.",
+                collection.posts_dir.to_string_lossy()
+            );
+        }
+        cli::Commands::List(list) => {
+            let config =
+                Config::read(&list.config).wrap_err("failed reading the gempost config file")?;
+
+            let collection = config.collection_or_main(list.collection.as_deref())?;
+
+            let warn_handler = |msg: &str| eprintln!("Warning: {}", msg);
+
+            let feed = Feed::from_config(&config, &collection, warn_handler)
+                .wrap_err("failed reading the gemlog posts")?;
+
+            let filter = ListFilter {
+                category: list.category,
+                drafts: list.drafts,
+                since: list.since,
+            };
+
+            print!("{}", list_posts(feed, &filter, list.json)?);
+        }
+        cli::Commands::Publish(publish) => {
+            let config =
+                Config::read(&publish.config).wrap_err("failed reading the gempost config file")?;
+
+            let collection = config.collection_or_main(publish.collection.as_deref())?;
+
+            publish_post(&collection.posts_dir, &publish.slug)
+                .wrap_err("failed publishing the post")?;
+        }
+        cli::Commands::Touch(touch) => {
+            let config =
+                Config::read(&touch.config).wrap_err("failed reading the gempost config file")?;
+
+            let collection = config.collection_or_main(touch.collection.as_deref())?;
+
+            touch_post(&collection.posts_dir, &touch.slug, touch.note.as_deref())
+                .wrap_err("failed updating the post")?;
+        }
+        cli::Commands::Rename(rename) => {
+            let config =
+                Config::read(&rename.config).wrap_err("failed reading the gempost config file")?;
+
+            let collection = config.collection_or_main(rename.collection.as_deref())?;
+
+            let count = rename_post(
+                &rename.config,
+                &config,
+                &collection,
+                &rename.old_slug,
+                &rename.new_slug,
+            )
+            .wrap_err("failed renaming the post")?;
+
+            println!("Updated links in {count} pages.");
+        }
     }
 
     Ok(())
@@ -0,0 +1,380 @@
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use crate::gemtext;
+
+// A link found in the text of a block, which becomes a link line after the block.
+struct PendingLink {
+    url: String,
+    label: String,
+}
+
+// Gemtext has no inline formatting or nesting, so the conversion works a block at a time. Text is
+// collected into `line` until the block ends, and the links in each top-level block are written
+// out as link lines after it.
+#[derive(Default)]
+struct Converter {
+    output: Vec<String>,
+    line: String,
+    links: Vec<PendingLink>,
+    // The label of the link we're currently inside of, if any.
+    link: Option<(String, String)>,
+    // The alt text of the image we're currently inside of, if any.
+    image: Option<(String, String)>,
+    // For each list we're inside of, the number of the next item if it's an ordered list.
+    lists: Vec<Option<u64>>,
+    quote_depth: usize,
+    in_code_block: bool,
+}
+
+impl Converter {
+    fn quote_prefix(&self) -> &'static str {
+        if self.quote_depth > 0 {
+            "> "
+        } else {
+            ""
+        }
+    }
+
+    fn is_top_level(&self) -> bool {
+        self.lists.is_empty() && self.quote_depth == 0
+    }
+
+    // Write out the text collected so far, one gemtext line per line of text.
+    fn flush_text(&mut self) {
+        let text = std::mem::take(&mut self.line);
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let line = if self.quote_depth > 0 {
+                format!("> {line}")
+            } else {
+                gemtext::escape(line)
+            };
+
+            self.output.push(line);
+        }
+    }
+
+    // Write out the text collected so far as a list item.
+    fn flush_item(&mut self) {
+        let text = std::mem::take(&mut self.line);
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if text.is_empty() {
+            return;
+        }
+
+        let number = match self.lists.last_mut() {
+            Some(Some(number)) => {
+                *number += 1;
+                format!("{}. ", *number - 1)
+            }
+            _ => String::new(),
+        };
+
+        let prefix = self.quote_prefix();
+        self.output.push(format!("{prefix}* {number}{text}"));
+    }
+
+    // Finish a top-level block by writing out its links and leaving a blank line after it.
+    fn end_block(&mut self) {
+        if !self.is_top_level() {
+            return;
+        }
+
+        for PendingLink { url, label } in self.links.drain(..) {
+            if label.is_empty() || label == url {
+                self.output.push(format!("=> {url}"));
+            } else {
+                self.output.push(format!("=> {url} {label}"));
+            }
+        }
+
+        self.push_blank_line();
+    }
+
+    // Leave a blank line after the last line, unless there already is one.
+    fn push_blank_line(&mut self) {
+        if self.output.last().is_some_and(|line| !line.is_empty()) {
+            self.output.push(String::new());
+        }
+    }
+
+    fn add_link(&mut self, url: String, label: String) {
+        let label = label.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        // Don't repeat a link that appears more than once in the same block.
+        if !self.links.iter().any(|link| link.url == url) {
+            self.links.push(PendingLink { url, label });
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if let Some((_, alt)) = &mut self.image {
+            alt.push_str(text);
+            return;
+        }
+
+        if let Some((_, label)) = &mut self.link {
+            label.push_str(text);
+        }
+
+        self.line.push_str(text);
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::BlockQuote(_) => self.quote_depth += 1,
+            Tag::CodeBlock(kind) => {
+                let alt = match kind {
+                    CodeBlockKind::Fenced(info) => info.to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+
+                // Gemtext can't nest a preformatted block in a list item or quote, so we write out
+                // the text before the block first, and end the quote with a blank line so the block
+                // doesn't look like part of it.
+                if self.lists.is_empty() {
+                    self.flush_text();
+                } else {
+                    self.flush_item();
+                }
+
+                if self.quote_depth > 0 {
+                    self.push_blank_line();
+                }
+
+                self.in_code_block = true;
+                self.output.push(format!("```{alt}").trim_end().to_owned());
+            }
+            Tag::List(start) => {
+                // A list inside of a list item ends the text of that item.
+                if !self.lists.is_empty() {
+                    self.flush_item();
+                }
+
+                self.lists.push(start);
+            }
+            Tag::Item => self.line.clear(),
+            Tag::Link { dest_url, .. } => self.link = Some((dest_url.to_string(), String::new())),
+            Tag::Image { dest_url, .. } => {
+                self.image = Some((dest_url.to_string(), String::new()));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                if self.lists.is_empty() {
+                    self.flush_text();
+                    self.end_block();
+                } else {
+                    // Paragraphs in a list item are joined into a single line.
+                    self.line.push(' ');
+                }
+            }
+            TagEnd::Heading(level) => {
+                let level = match level {
+                    HeadingLevel::H1 => 1,
+                    HeadingLevel::H2 => 2,
+                    // Gemtext only has three levels of headings.
+                    _ => 3,
+                };
+
+                let text = std::mem::take(&mut self.line);
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                let prefix = self.quote_prefix();
+
+                self.output
+                    .push(format!("{prefix}{} {text}", "#".repeat(level)));
+                self.end_block();
+            }
+            TagEnd::BlockQuote(_) => {
+                self.flush_text();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+                self.end_block();
+            }
+            TagEnd::CodeBlock => {
+                self.in_code_block = false;
+                self.output.push("```".to_owned());
+
+                // Any more of the quote after the block starts a new quote.
+                if self.quote_depth > 0 {
+                    self.push_blank_line();
+                }
+
+                self.end_block();
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                self.end_block();
+            }
+            TagEnd::Item => self.flush_item(),
+            TagEnd::Link => {
+                if let Some((url, label)) = self.link.take() {
+                    self.add_link(url, label);
+                }
+            }
+            TagEnd::Image => {
+                if let Some((url, alt)) = self.image.take() {
+                    self.add_link(url, alt);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) if self.in_code_block => {
+                self.output.extend(text.lines().map(str::to_owned));
+            }
+            Event::Text(text) => self.push_text(&text),
+            Event::Code(code) => self.push_text(&format!("`{code}`")),
+            Event::SoftBreak => self.push_text(" "),
+            Event::HardBreak => self.push_text("\n"),
+            Event::TaskListMarker(checked) => {
+                self.push_text(if checked { "[x] " } else { "[ ] " });
+            }
+            // Gemini clients don't render HTML, so we leave it out.
+            Event::Html(_) | Event::InlineHtml(_) => {}
+            _ => {}
+        }
+    }
+}
+
+// Convert a stream of Markdown events to gemtext. This lets other formats which can be expressed as
+// Markdown events share the same conversion.
+pub fn events_to_gemtext<'a>(events: impl IntoIterator<Item = Event<'a>>) -> String {
+    let mut converter = Converter::default();
+
+    for event in events {
+        converter.event(event);
+    }
+
+    // Remove the blank line after the last block.
+    while converter.output.last().is_some_and(String::is_empty) {
+        converter.output.pop();
+    }
+
+    let mut output = converter.output.join("\n");
+
+    if !output.is_empty() {
+        output.push('\n');
+    }
+
+    output
+}
+
+// Convert a Markdown document to gemtext.
+//
+// Headings, lists, quotes, and code blocks become their gemtext equivalents. Since gemtext doesn't
+// have inline links, the links and images in each paragraph, list, or quote become link lines
+// after it. Inline formatting and HTML are removed.
+pub fn to_gemtext(markdown: &str) -> String {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    events_to_gemtext(Parser::new_ext(markdown, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_headings_and_paragraphs() {
+        let markdown = "# Title\n\nSome *emphasized*\ntext.\n\n#### Deep heading\n";
+        assert_eq!(
+            to_gemtext(markdown),
+            "# Title\n\nSome emphasized text.\n\n### Deep heading\n"
+        );
+    }
+
+    #[test]
+    fn moves_links_after_their_block() {
+        let markdown = "See [one](gemini://one) and [two](gemini://two), or [one](gemini://one).\n";
+        assert_eq!(
+            to_gemtext(markdown),
+            "See one and two, or one.\n=> gemini://one one\n=> gemini://two two\n"
+        );
+    }
+
+    #[test]
+    fn converts_nested_blockquotes() {
+        let markdown = "> Outer\n>\n> > Inner\n>\n> Outer again\n\nAfter\n";
+        assert_eq!(
+            to_gemtext(markdown),
+            "> Outer\n> Inner\n> Outer again\n\nAfter\n"
+        );
+    }
+
+    #[test]
+    fn converts_nested_lists() {
+        let markdown = "- One\n  1. Two\n  2. Three\n- Four\n";
+        assert_eq!(
+            to_gemtext(markdown),
+            "* One\n* 1. Two\n* 2. Three\n* Four\n"
+        );
+    }
+
+    #[test]
+    fn converts_lists_in_blockquotes() {
+        let markdown = "> Quote\n>\n> - Item [link](gemini://link)\n";
+        assert_eq!(
+            to_gemtext(markdown),
+            "> Quote\n> * Item link\n=> gemini://link link\n"
+        );
+    }
+
+    #[test]
+    fn converts_code_blocks() {
+        let markdown = "```rust\nfn main() {}\n\n# not a heading\n```\n";
+        assert_eq!(
+            to_gemtext(markdown),
+            "```rust\nfn main() {}\n\n# not a heading\n```\n"
+        );
+    }
+
+    #[test]
+    fn writes_list_items_before_their_code_blocks() {
+        let markdown = "1. Run this:\n\n   ```sh\n   make\n   ```\n2. Done\n";
+        assert_eq!(
+            to_gemtext(markdown),
+            "* 1. Run this:\n```sh\nmake\n```\n* 2. Done\n"
+        );
+    }
+
+    #[test]
+    fn ends_quotes_before_their_code_blocks() {
+        let markdown = "> Before\n>\n> ```\n> code\n> ```\n>\n> After\n\nOutside\n";
+        assert_eq!(
+            to_gemtext(markdown),
+            "> Before\n\n```\ncode\n```\n\n> After\n\nOutside\n"
+        );
+
+        let markdown = "> ```\n> code\n> ```\n\nOutside\n";
+        assert_eq!(to_gemtext(markdown), "```\ncode\n```\n\nOutside\n");
+    }
+
+    #[test]
+    fn escapes_text_that_looks_like_gemtext() {
+        assert_eq!(
+            to_gemtext("\\=> not a link\n"),
+            gemtext::escape("=> not a link") + "\n"
+        );
+    }
+
+    #[test]
+    fn ignores_unbalanced_block_quote_ends() {
+        let events = [
+            Event::Start(Tag::Paragraph),
+            Event::Text("Text".into()),
+            Event::End(TagEnd::Paragraph),
+            Event::End(TagEnd::BlockQuote(None)),
+        ];
+
+        assert_eq!(events_to_gemtext(events), "Text\n");
+    }
+}
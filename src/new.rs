@@ -2,11 +2,12 @@ use std::fs::OpenOptions;
 use std::io::{self, Write};
 use std::path::Path;
 
-use chrono::{Local, SecondsFormat};
+use chrono::SecondsFormat;
 use eyre::{bail, WrapErr};
 use tera::{Context, Tera};
 use uuid::Uuid;
 
+use crate::clock;
 use crate::error::Error;
 
 const METADATA_TEMPLATE: &str = include_str!("metadata.yaml.tera");
@@ -22,7 +23,7 @@ fn generate_metadata_file(template: &str, title: Option<&str>) -> eyre::Result<S
     context.insert("title", title.unwrap_or_default());
     context.insert(
         "timestamp",
-        &Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+        &clock::now()?.to_rfc3339_opts(SecondsFormat::Secs, false),
     );
 
     tera.render("metadata", &context)
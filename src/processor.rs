@@ -0,0 +1,122 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Stdio;
+use std::thread;
+
+use eyre::{bail, eyre, WrapErr};
+
+use crate::config::ProcessorConfig;
+use crate::error::Error;
+use crate::hooks::shell_command;
+
+// Pipe a post body through a processor command using the system shell, returning what the command
+// writes to stdout.
+pub fn run_processor(
+    processor: &ProcessorConfig,
+    body: String,
+    post_path: &Path,
+) -> eyre::Result<String> {
+    let mut child = shell_command(&processor.command)
+        .env("GEMPOST_PROCESSOR", &processor.name)
+        .env("GEMPOST_POST_FILE", post_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .wrap_err(format!("failed running processor: {}", processor.command))?;
+
+    let mut stdin = child.stdin.take().ok_or(eyre!(
+        "The processor's stdin was not captured. This is a bug."
+    ))?;
+
+    // We write the body from another thread so that a processor which writes output before it has
+    // read all of its input can't deadlock with us.
+    let writer = thread::spawn(move || match stdin.write_all(body.as_bytes()) {
+        // The processor doesn't have to read its input.
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    });
+
+    let output = child
+        .wait_with_output()
+        .wrap_err(format!("failed running processor: {}", processor.command))?;
+
+    writer
+        .join()
+        .map_err(|_| eyre!("The thread writing to the processor panicked. This is a bug."))?
+        .wrap_err("failed writing post body to processor")?;
+
+    if !output.status.success() {
+        bail!(Error::ProcessorFailed {
+            name: processor.name.clone(),
+            path: post_path.to_owned(),
+            status: output.status.to_string(),
+        });
+    }
+
+    String::from_utf8(output.stdout).wrap_err(format!(
+        "output of processor `{}` is not valid UTF-8",
+        processor.name
+    ))
+}
+
+// These use POSIX shell utilities.
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+
+    fn processor(command: &str) -> ProcessorConfig {
+        ProcessorConfig {
+            name: String::from("test"),
+            command: command.to_owned(),
+            extensions: Vec::new(),
+        }
+    }
+
+    fn run(command: &str, body: &str) -> eyre::Result<String> {
+        run_processor(&processor(command), body.to_owned(), Path::new("post.gmi"))
+    }
+
+    #[test]
+    fn pipes_the_body_through_the_command() {
+        assert_eq!(
+            run("tr a-z A-Z", "Hello, world!\n").unwrap(),
+            "HELLO, WORLD!\n"
+        );
+        assert_eq!(
+            run("echo \"$GEMPOST_PROCESSOR $GEMPOST_POST_FILE\"", "").unwrap(),
+            "test post.gmi\n"
+        );
+    }
+
+    #[test]
+    fn handles_large_bodies() {
+        let body = "line\n".repeat(100_000);
+        assert_eq!(run("cat", &body).unwrap(), body);
+    }
+
+    #[test]
+    fn fails_when_the_command_fails() {
+        let err = run("cat > /dev/null; exit 2", "Body\n").unwrap_err();
+
+        match err.downcast_ref::<Error>() {
+            Some(Error::ProcessorFailed { name, path, .. }) => {
+                assert_eq!(name, "test");
+                assert_eq!(path, Path::new("post.gmi"));
+            }
+            _ => panic!("unexpected error: {err:?}"),
+        }
+    }
+
+    #[test]
+    fn fails_when_the_output_is_not_utf8() {
+        let err = run("printf '\\377'", "").unwrap_err();
+        assert!(err.to_string().contains("not valid UTF-8"), "{err}");
+    }
+
+    #[test]
+    fn allows_commands_that_do_not_read_the_body() {
+        // The body is larger than a pipe buffer, so writing it fails once the command exits.
+        let body = "line\n".repeat(100_000);
+        assert_eq!(run("echo replaced", &body).unwrap(), "replaced\n");
+    }
+}
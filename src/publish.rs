@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::SecondsFormat;
+use eyre::{bail, WrapErr};
+
+use crate::clock;
+use crate::entry::RawEntryMetadata;
+use crate::error::Error;
+use crate::sidecar;
+
+// Promote a draft post to a published post, setting its publish and update times to now.
+pub fn publish_post(posts_dir: &Path, slug: &str) -> eyre::Result<()> {
+    let path = sidecar::find(posts_dir, slug)?;
+
+    let metadata = RawEntryMetadata::read(&path).wrap_err(format!(
+        "failed reading metadata file: {}",
+        path.to_string_lossy()
+    ))?;
+
+    if metadata.draft != Some(true) {
+        bail!(Error::PostNotDraft {
+            slug: slug.to_owned()
+        });
+    }
+
+    let source = fs::read_to_string(&path).wrap_err("failed reading post metadata file")?;
+
+    let timestamp = sidecar::quote(&clock::now()?.to_rfc3339_opts(SecondsFormat::Secs, false));
+
+    let source = sidecar::set_value(&source, "draft", "false");
+    let source = sidecar::set_value(&source, "published", &timestamp);
+    let source = sidecar::set_value(&source, "updated", &timestamp);
+
+    fs::write(&path, source).wrap_err("failed writing post metadata file")?;
+
+    Ok(())
+}
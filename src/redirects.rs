@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+use url::Url;
+
+use crate::build::url_to_filepath;
+use crate::config::Config;
+use crate::feed::Feed;
+
+// Agate reads the metadata for the files in each directory from a file with this name.
+const AGATE_META_FILE_NAME: &str = ".meta";
+
+// Redirects are for content which has moved for good, so they use the permanent redirect status.
+const REDIRECT_STATUS: &str = "31";
+
+const GENERATED_HEADER: &str = "Generated by gempost. Changes to this file will be overwritten.";
+
+// Every redirect in the capsule, from a URL path to the URL it redirects to. These come from the
+// `redirects` in the config file and the `aliases` of each published post.
+pub fn collect_redirects(
+    config: &Config,
+    feeds: &[Feed],
+    warn_handler: impl Fn(&str),
+) -> eyre::Result<BTreeMap<String, Url>> {
+    let mut redirects = BTreeMap::new();
+
+    for (source, target) in &config.redirects {
+        let target = config
+            .url
+            .join(target)
+            .wrap_err(format!("failed resolving the redirect from: {source}"))?;
+
+        redirects.insert(source.clone(), target);
+    }
+
+    for entry in feeds.iter().flat_map(|feed| &feed.entries) {
+        for alias in &entry.metadata.aliases {
+            match redirects.get(alias) {
+                Some(target) if *target == entry.url => {}
+                Some(_) => warn_handler(&format!(
+                    "There is already a redirect from this alias to somewhere else, so it is ignored: {alias}"
+                )),
+                None => {
+                    redirects.insert(alias.clone(), entry.url.clone());
+                }
+            }
+        }
+    }
+
+    Ok(redirects)
+}
+
+// Warn about redirects from paths where there is also a file, since servers may serve the file
+// instead of redirecting. This must be called after the capsule is built.
+pub fn check_collisions(
+    config: &Config,
+    redirects: &BTreeMap<String, Url>,
+    warn_handler: impl Fn(&str),
+) {
+    for source in redirects.keys() {
+        if url_to_filepath(&config.static_dir, source).exists() {
+            warn_handler(&format!(
+                "This redirect is from the path of a file in your static directory, so your server may serve the file instead: {source}"
+            ));
+        } else if url_to_filepath(&config.public_dir, source).exists() {
+            warn_handler(&format!(
+                "This redirect is from the path of a page gempost generates, so your server may serve the page instead: {source}"
+            ));
+        }
+    }
+}
+
+// Escape the characters which have a special meaning in glob patterns.
+fn escape_glob(pattern: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in pattern.chars() {
+        match c {
+            '*' | '?' | '[' | ']' => escaped.push_str(&format!("[{c}]")),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+// Write a `.meta` file for Agate into each directory with redirects in it. If there is already a
+// `.meta` file in the public directory, like one copied from the static directory, the redirects
+// are added to the end of it.
+fn write_agate_meta(public_dir: &Path, redirects: &BTreeMap<String, Url>) -> eyre::Result<()> {
+    let mut lines_by_dir: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+
+    for (source, target) in redirects {
+        let (dir, file_name) = source
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .unwrap_or_default();
+
+        if file_name.is_empty() {
+            continue;
+        }
+
+        lines_by_dir
+            .entry(url_to_filepath(public_dir, dir))
+            .or_default()
+            .push(format!(
+                "{}: {REDIRECT_STATUS} {target}\n",
+                escape_glob(file_name)
+            ));
+    }
+
+    for (dir, lines) in lines_by_dir {
+        let meta_path = dir.join(AGATE_META_FILE_NAME);
+
+        let mut contents = match fs::read_to_string(&meta_path) {
+            Ok(contents) if !contents.is_empty() && !contents.ends_with('\n') => contents + "\n",
+            Ok(contents) => contents,
+            Err(_) => String::new(),
+        };
+
+        contents.push_str(&format!("# {GENERATED_HEADER}\n"));
+        contents.extend(lines);
+
+        fs::create_dir_all(&dir).wrap_err("failed creating directory for Agate .meta file")?;
+        fs::write(&meta_path, contents).wrap_err("failed writing Agate .meta file")?;
+    }
+
+    Ok(())
+}
+
+// Generate `location` blocks for gmid, to be included in the `server` block for the capsule.
+fn generate_gmid(redirects: &BTreeMap<String, Url>) -> String {
+    let mut output = format!("# {GENERATED_HEADER}\n");
+
+    for (source, target) in redirects {
+        // The location is a glob pattern, and gmid expands `%` sequences in the target.
+        let location = escape_glob(source).replace('"', "\\\"");
+        let target = target.as_str().replace('%', "%%").replace('"', "\\\"");
+
+        output.push_str(&format!(
+            "\nlocation \"{location}\" {{\n\tblock return {REDIRECT_STATUS} \"{target}\"\n}}\n"
+        ));
+    }
+
+    output
+}
+
+// Escape the characters which have a special meaning in regular expressions.
+fn escape_regex(pattern: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in pattern.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+fn toml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Generate a `[PermRedirects]` section for Molly Brown's config file. Molly Brown can only redirect
+// to other paths on the same capsule, so other redirects are left out.
+fn generate_molly_brown(
+    capsule_url: &Url,
+    redirects: &BTreeMap<String, Url>,
+    warn_handler: impl Fn(&str),
+) -> String {
+    let mut output = format!("# {GENERATED_HEADER}\n\n[PermRedirects]\n");
+
+    for (source, target) in redirects {
+        // URLs with the `gemini` scheme don't have a comparable origin, so we compare the parts
+        // of it ourselves.
+        let same_capsule = target.scheme() == capsule_url.scheme()
+            && target.host() == capsule_url.host()
+            && target.port() == capsule_url.port();
+
+        if !same_capsule || target.query().is_some() {
+            warn_handler(&format!(
+                "Molly Brown can only redirect to other paths on the same capsule, so this redirect is left out of its config: {source}"
+            ));
+            continue;
+        }
+
+        // Sources are regular expressions, and `$` in the target refers to their capture groups.
+        let pattern = format!("^{}$", escape_regex(source));
+        let path = target.path().replace('$', "$$");
+
+        output.push_str(&format!(
+            "{} = {}\n",
+            toml_string(&pattern),
+            toml_string(&path)
+        ));
+    }
+
+    output
+}
+
+// Generate the redirect configuration for each Gemini server enabled in the config file.
+pub fn write_redirects(
+    config: &Config,
+    redirects: &BTreeMap<String, Url>,
+    warn_handler: impl Fn(&str),
+) -> eyre::Result<()> {
+    let servers = &config.redirect_servers;
+
+    if servers.agate {
+        write_agate_meta(&config.public_dir, redirects)
+            .wrap_err("failed writing Agate redirects")?;
+    }
+
+    if let Some(path) = &servers.gmid {
+        fs::write(path, generate_gmid(redirects)).wrap_err("failed writing gmid redirects")?;
+    }
+
+    if let Some(path) = &servers.molly_brown {
+        fs::write(
+            path,
+            generate_molly_brown(&config.url, redirects, warn_handler),
+        )
+        .wrap_err("failed writing Molly Brown redirects")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::config::tests::temp_dir;
+
+    // A source with characters which are special to every server's config format.
+    const SPECIAL_SOURCE: &str = "/old/a*b%c$d\"e.gmi";
+
+    fn redirects(redirects: &[(&str, &str)]) -> BTreeMap<String, Url> {
+        redirects
+            .iter()
+            .map(|(source, target)| ((*source).to_owned(), Url::parse(target).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn escapes_gmid_locations_and_targets() {
+        let output = generate_gmid(&redirects(&[
+            (SPECIAL_SOURCE, "gemini://example.com/new/100%25$1.gmi"),
+            ("/away.gmi", "gemini://elsewhere.example/"),
+        ]));
+
+        assert_eq!(
+            output,
+            r#"# Generated by gempost. Changes to this file will be overwritten.
+
+location "/away.gmi" {
+	block return 31 "gemini://elsewhere.example/"
+}
+
+location "/old/a[*]b%c$d\"e.gmi" {
+	block return 31 "gemini://example.com/new/100%%25$1.gmi"
+}
+"#
+        );
+    }
+
+    #[test]
+    fn anchors_and_escapes_molly_brown_redirects() {
+        let warnings = RefCell::new(Vec::new());
+        let output = generate_molly_brown(
+            &Url::parse("gemini://example.com/").unwrap(),
+            &redirects(&[
+                (SPECIAL_SOURCE, "gemini://example.com/new/100%25$1.gmi"),
+                ("/away.gmi", "gemini://elsewhere.example/"),
+                ("/port.gmi", "gemini://example.com:1966/port.gmi"),
+            ]),
+            |warning| warnings.borrow_mut().push(warning.to_owned()),
+        );
+
+        assert_eq!(
+            output,
+            r#"# Generated by gempost. Changes to this file will be overwritten.
+
+[PermRedirects]
+"^/old/a\\*b%c\\$d\"e\\.gmi$" = "/new/100%25$$1.gmi"
+"#
+        );
+
+        // Redirects to other hosts or ports are left out.
+        assert_eq!(
+            warnings.into_inner(),
+            [
+                "Molly Brown can only redirect to other paths on the same capsule, so this redirect is left out of its config: /away.gmi",
+                "Molly Brown can only redirect to other paths on the same capsule, so this redirect is left out of its config: /port.gmi",
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_agate_meta_files() {
+        let dir = temp_dir("redirects-agate");
+        fs::create_dir_all(dir.join("old")).unwrap();
+        fs::write(
+            dir.join("old").join(AGATE_META_FILE_NAME),
+            "*.txt: text/plain",
+        )
+        .unwrap();
+
+        write_agate_meta(
+            &dir,
+            &redirects(&[
+                (SPECIAL_SOURCE, "gemini://example.com/new/100%25$1.gmi"),
+                ("/moved/", "gemini://example.com/new/"),
+                ("/away.gmi", "gemini://elsewhere.example/"),
+            ]),
+        )
+        .unwrap();
+
+        // The trailing `/` of a source is ignored, so it redirects the directory itself.
+        assert_eq!(
+            fs::read_to_string(dir.join(AGATE_META_FILE_NAME)).unwrap(),
+            "# Generated by gempost. Changes to this file will be overwritten.\n\
+             away.gmi: 31 gemini://elsewhere.example/\n\
+             moved: 31 gemini://example.com/new/\n"
+        );
+
+        // Redirects are added to the end of an existing `.meta` file.
+        assert_eq!(
+            fs::read_to_string(dir.join("old").join(AGATE_META_FILE_NAME)).unwrap(),
+            "*.txt: text/plain\n\
+             # Generated by gempost. Changes to this file will be overwritten.\n\
+             a[*]b%c$d\"e.gmi: 31 gemini://example.com/new/100%25$1.gmi\n"
+        );
+    }
+}
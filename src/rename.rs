@@ -0,0 +1,485 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eyre::{bail, WrapErr};
+use url::{Position, Url};
+
+use crate::config::{CollectionConfig, Config};
+use crate::entry::{is_valid_slug, EntryMetadata, PostLocationParams};
+use crate::error::Error;
+use crate::feed::locate_post;
+use crate::file_ext::{METADATA_FILE_EXT, POST_FILE_EXT};
+use crate::gemtext;
+use crate::sidecar;
+use crate::template::find_files;
+
+const UNEDITABLE_REDIRECTS_REASON: &str =
+    "The `redirects` must be written with one redirect per line so gempost can add to them.";
+
+// The files in the posts directory with the given slug, including the metadata file.
+fn post_files(posts_dir: &Path, slug: &str) -> eyre::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    for dir_entry in fs::read_dir(posts_dir).wrap_err("failed reading posts directory")? {
+        let path = dir_entry.wrap_err("failed reading posts directory")?.path();
+
+        if path.file_stem().is_some_and(|stem| stem == slug) {
+            paths.push(path);
+        }
+    }
+
+    // Sort the paths so the order doesn't depend on the order we read them from the filesystem.
+    paths.sort();
+
+    Ok(paths)
+}
+
+// If `link`, found on the page at `page_url`, points to `old_url`, return a link to `new_url`
+// written the same way, whether that's a full URL, an absolute path, or a relative path.
+fn rewrite_link(link: &str, page_url: &Url, old_url: &Url, new_url: &Url) -> Option<String> {
+    let mut resolved = page_url.join(link).ok()?;
+    let fragment = resolved.fragment().map(ToOwned::to_owned);
+    resolved.set_fragment(None);
+
+    if resolved != *old_url {
+        return None;
+    }
+
+    let mut target = new_url.clone();
+    target.set_fragment(fragment.as_deref());
+
+    Some(if Url::parse(link).is_ok() || link.starts_with("//") {
+        target.to_string()
+    } else if link.starts_with('/') {
+        target[Position::BeforePath..].to_owned()
+    } else {
+        match page_url.make_relative(&target) {
+            // A link from a page to itself is relative to the page's filename.
+            Some(relative) if relative.is_empty() || relative.starts_with('#') => {
+                let filename = target
+                    .path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .unwrap_or_default()
+                    .to_owned();
+
+                filename + &relative
+            }
+            Some(relative) => relative,
+            None => target.to_string(),
+        }
+    })
+}
+
+// The gemtext pages in the capsule which can link to a post, along with their URLs. These are the
+// gemtext posts in every collection and the gemtext files in the static directory.
+fn gemtext_pages(config: &Config) -> eyre::Result<Vec<(PathBuf, Url)>> {
+    let mut pages = Vec::new();
+
+    for collection in config.all_collections() {
+        if !collection.posts_dir.is_dir() {
+            continue;
+        }
+
+        let mut post_paths = fs::read_dir(&collection.posts_dir)
+            .wrap_err("failed reading posts directory")?
+            .map(|dir_entry| dir_entry.map(|dir_entry| dir_entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .wrap_err("failed reading posts directory")?;
+
+        post_paths.sort();
+
+        for post_path in post_paths {
+            if post_path
+                .extension()
+                .map_or(true, |ext| ext != POST_FILE_EXT)
+            {
+                continue;
+            }
+
+            // Posts without a metadata file aren't published, so they can be skipped.
+            let metadata_path = post_path.with_extension(METADATA_FILE_EXT);
+
+            if !metadata_path.exists() {
+                continue;
+            }
+
+            let metadata = EntryMetadata::read(&metadata_path, &config.authors)?;
+            let slug = post_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            let location = locate_post(
+                config,
+                &collection,
+                PostLocationParams {
+                    metadata: &metadata,
+                    slug: &slug,
+                },
+            )?;
+
+            pages.push((post_path, location.url));
+        }
+    }
+
+    if config.static_dir.is_dir() {
+        let mut static_files = Vec::new();
+        find_files(&config.static_dir, &config.static_dir, &mut static_files)
+            .wrap_err("failed reading static directory")?;
+
+        for (path, relative_path) in static_files {
+            if path.extension().map_or(true, |ext| ext != POST_FILE_EXT) {
+                continue;
+            }
+
+            let url = config
+                .url
+                .join(&relative_path)
+                .wrap_err("failed getting the URL of a static file")?;
+
+            pages.push((path, url));
+        }
+    }
+
+    Ok(pages)
+}
+
+// Record a redirect from the old path of a post to its new path in the config file. Existing
+// redirects to the old path are pointed at the new path instead, so readers aren't redirected more
+// than once.
+fn add_redirect(
+    source: &str,
+    config: &Config,
+    config_path: &Path,
+    old_path: &str,
+    new_path: &str,
+) -> eyre::Result<String> {
+    let invalid_redirects = || Error::InvalidConfigFile {
+        path: config_path.to_owned(),
+        reason: String::from(UNEDITABLE_REDIRECTS_REASON),
+    };
+
+    // If the post is being renamed back to an old path, the redirect from that path is no longer
+    // needed.
+    let mut source =
+        sidecar::remove_map_entry(source, "redirects", new_path).ok_or_else(invalid_redirects)?;
+
+    for (from, to) in &config.redirects {
+        if to == old_path && from != new_path {
+            source = sidecar::set_map_entry(&source, "redirects", from, &sidecar::quote(new_path))
+                .ok_or_else(invalid_redirects)?;
+        }
+    }
+
+    Ok(
+        sidecar::set_map_entry(&source, "redirects", old_path, &sidecar::quote(new_path))
+            .ok_or_else(invalid_redirects)?,
+    )
+}
+
+// Put back the files changed by `rename_post` after one of its changes failed. This is done on a
+// best-effort basis, since we're already reporting an error.
+fn undo_changes(written: &[(&Path, &str)], renamed: &[(&Path, &Path)]) {
+    for (old_path, new_path) in renamed.iter().rev() {
+        let _ = fs::rename(new_path, old_path);
+    }
+
+    for (path, contents) in written.iter().rev() {
+        let _ = fs::write(path, contents);
+    }
+}
+
+// Give a post a new slug, keeping its ID. Links to the post from other gemtext pages are updated,
+// and a redirect from the post's old path is added to the config file. This returns the number of
+// pages whose links were updated.
+pub fn rename_post(
+    config_path: &Path,
+    config: &Config,
+    collection: &CollectionConfig,
+    old_slug: &str,
+    new_slug: &str,
+) -> eyre::Result<usize> {
+    if !is_valid_slug(new_slug) {
+        bail!(Error::InvalidSlug {
+            slug: new_slug.to_owned()
+        });
+    }
+
+    let metadata_path = sidecar::find(&collection.posts_dir, old_slug)?;
+    let metadata = EntryMetadata::read(&metadata_path, &config.authors)?;
+
+    if !post_files(&collection.posts_dir, new_slug)?.is_empty() {
+        bail!(Error::PostAlreadyExists {
+            slug: new_slug.to_owned()
+        });
+    }
+
+    let old_location = locate_post(
+        config,
+        collection,
+        PostLocationParams {
+            metadata: &metadata,
+            slug: old_slug,
+        },
+    )?;
+
+    let new_location = locate_post(
+        config,
+        collection,
+        PostLocationParams {
+            metadata: &metadata,
+            slug: new_slug,
+        },
+    )?;
+
+    let renames = post_files(&collection.posts_dir, old_slug)?
+        .into_iter()
+        .map(|old_path| {
+            let mut new_path = collection.posts_dir.join(new_slug);
+
+            if let Some(ext) = old_path.extension() {
+                new_path.set_extension(ext);
+            }
+
+            (old_path, new_path)
+        })
+        .collect::<Vec<_>>();
+
+    // Work out all the changes before making any of them, so that a problem with one file doesn't
+    // leave the capsule half-renamed.
+    let mut rewrites = Vec::new();
+
+    for (path, page_url) in gemtext_pages(config)? {
+        let body = fs::read_to_string(&path)
+            .wrap_err(format!("failed reading page: {}", path.to_string_lossy()))?;
+
+        // The post being renamed may link to itself, and those links need to be relative to its
+        // new URL. Its links are updated before it's renamed.
+        let page_url = if renames.iter().any(|(old_path, _)| *old_path == path) {
+            new_location.url.clone()
+        } else {
+            page_url
+        };
+
+        let rewritten = gemtext::rewrite_links(&body, |link| {
+            rewrite_link(link, &page_url, &old_location.url, &new_location.url)
+        });
+
+        if let Some(rewritten) = rewritten {
+            rewrites.push((path, body, rewritten));
+        }
+    }
+
+    // Drafts haven't been published, so nobody is linking to them.
+    let config_change = if !metadata.draft && old_location.url != new_location.url {
+        let source = fs::read_to_string(config_path).wrap_err("failed reading config file")?;
+
+        let new_source = add_redirect(
+            &source,
+            config,
+            config_path,
+            old_location.url.path(),
+            new_location.url.path(),
+        )?;
+
+        Some((source, new_source))
+    } else {
+        None
+    };
+
+    // Keep track of the changes we've made, so they can be undone if a later one fails.
+    let mut written = Vec::new();
+    let mut renamed = Vec::new();
+
+    let result = (|| {
+        if let Some((source, new_source)) = &config_change {
+            written.push((config_path, source.as_str()));
+            fs::write(config_path, new_source).wrap_err("failed writing config file")?;
+        }
+
+        for (path, body, rewritten) in &rewrites {
+            written.push((path.as_path(), body.as_str()));
+            fs::write(path, rewritten).wrap_err(format!(
+                "failed updating links in page: {}",
+                path.to_string_lossy()
+            ))?;
+        }
+
+        for (old_path, new_path) in &renames {
+            fs::rename(old_path, new_path).wrap_err(format!(
+                "failed renaming post file: {}",
+                old_path.to_string_lossy()
+            ))?;
+            renamed.push((old_path.as_path(), new_path.as_path()));
+        }
+
+        Ok::<_, eyre::Report>(())
+    })();
+
+    if result.is_err() {
+        undo_changes(&written, &renamed);
+    }
+
+    result?;
+
+    Ok(rewrites.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::{read_config, temp_dir};
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn rewrite(link: &str, page_url: &str) -> Option<String> {
+        rewrite_link(
+            link,
+            &url(page_url),
+            &url("gemini://example.com/posts/old.gmi"),
+            &url("gemini://example.com/posts/2024/new.gmi"),
+        )
+    }
+
+    #[test]
+    fn rewrites_relative_links() {
+        let page_url = "gemini://example.com/posts/other.gmi";
+
+        assert_eq!(
+            rewrite("old.gmi", page_url).as_deref(),
+            Some("2024/new.gmi")
+        );
+        assert_eq!(
+            rewrite("./old.gmi#part-2", page_url).as_deref(),
+            Some("2024/new.gmi#part-2")
+        );
+        assert_eq!(
+            rewrite("../posts/old.gmi", "gemini://example.com/about/me.gmi").as_deref(),
+            Some("../posts/2024/new.gmi")
+        );
+    }
+
+    #[test]
+    fn rewrites_absolute_links() {
+        let page_url = "gemini://example.com/index.gmi";
+
+        assert_eq!(
+            rewrite("/posts/old.gmi", page_url).as_deref(),
+            Some("/posts/2024/new.gmi")
+        );
+        assert_eq!(
+            rewrite("/posts/old.gmi#part-2", page_url).as_deref(),
+            Some("/posts/2024/new.gmi#part-2")
+        );
+    }
+
+    #[test]
+    fn rewrites_full_urls() {
+        let page_url = "gemini://example.com/index.gmi";
+
+        assert_eq!(
+            rewrite("gemini://example.com/posts/old.gmi#part-2", page_url).as_deref(),
+            Some("gemini://example.com/posts/2024/new.gmi#part-2")
+        );
+        assert_eq!(
+            rewrite("//example.com/posts/old.gmi", page_url).as_deref(),
+            Some("gemini://example.com/posts/2024/new.gmi")
+        );
+    }
+
+    #[test]
+    fn rewrites_links_from_a_post_to_itself() {
+        let page_url = "gemini://example.com/posts/2024/new.gmi";
+
+        assert_eq!(
+            rewrite("../old.gmi#part-2", page_url).as_deref(),
+            Some("new.gmi#part-2")
+        );
+    }
+
+    #[test]
+    fn leaves_other_links_alone() {
+        let page_url = "gemini://example.com/posts/other.gmi";
+
+        for link in [
+            "other.gmi",
+            "old.gmi?query",
+            "/old.gmi",
+            "gemini://elsewhere.example/posts/old.gmi",
+            "https://example.com/posts/old.gmi",
+        ] {
+            assert_eq!(rewrite(link, page_url), None, "{link}");
+        }
+    }
+
+    #[test]
+    fn renames_posts() {
+        let dir = temp_dir("rename-post");
+        let posts_dir = dir.join("posts");
+        fs::create_dir_all(&posts_dir).unwrap();
+
+        let config = read_config(
+            &dir,
+            &format!(
+                "title: Example\nurl: gemini://example.com/\nposts_dir: \"{}\"\nredirects:\n  /posts/older.gmi: /posts/old.gmi\n",
+                posts_dir.to_string_lossy()
+            ),
+        )
+        .unwrap();
+
+        for (slug, body) in [
+            ("old", "=> #part-2 Part 2\n=> old.gmi#part-3 Part 3\n"),
+            ("other", "=> old.gmi Old\n=> other.gmi Other\n"),
+        ] {
+            fs::write(posts_dir.join(format!("{slug}.gmi")), body).unwrap();
+            fs::write(
+                posts_dir.join(format!("{slug}.yaml")),
+                format!(
+                    "id: \"urn:{slug}\"\ntitle: \"{slug}\"\nupdated: \"2024-01-01T00:00:00Z\"\n"
+                ),
+            )
+            .unwrap();
+        }
+
+        let config_path = dir.join("gempost.yaml");
+        let count = rename_post(
+            &config_path,
+            &config,
+            &config.main_collection(),
+            "old",
+            "new",
+        )
+        .unwrap();
+        assert_eq!(count, 2);
+
+        assert!(!posts_dir.join("old.gmi").exists());
+        assert!(!posts_dir.join("old.yaml").exists());
+        assert!(posts_dir.join("new.yaml").exists());
+        assert_eq!(
+            fs::read_to_string(posts_dir.join("new.gmi")).unwrap(),
+            "=> #part-2 Part 2\n=> new.gmi#part-3 Part 3\n"
+        );
+        assert_eq!(
+            fs::read_to_string(posts_dir.join("other.gmi")).unwrap(),
+            "=> new.gmi Old\n=> other.gmi Other\n"
+        );
+
+        // The existing redirect to the old path now goes straight to the new path.
+        let config = Config::read(&config_path).unwrap();
+        assert_eq!(
+            config.redirects.into_iter().collect::<Vec<_>>(),
+            [
+                (
+                    String::from("/posts/old.gmi"),
+                    String::from("/posts/new.gmi")
+                ),
+                (
+                    String::from("/posts/older.gmi"),
+                    String::from("/posts/new.gmi")
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::BTreeSet;
+
+use crate::config::RobotsConfig;
+use crate::feed::Feed;
+
+// Posts marked `noindex` are disallowed for this virtual user agent.
+//
+// https://geminiprotocol.net/docs/companion/robots.gmi
+const NOINDEX_USER_AGENT: &str = "indexer";
+
+fn push_group(output: &mut String, user_agents: &[&str], disallow: &BTreeSet<String>) {
+    if !output.is_empty() {
+        output.push('\n');
+    }
+
+    for user_agent in user_agents {
+        output.push_str(&format!("User-agent: {user_agent}\n"));
+    }
+
+    // An empty `Disallow` allows everything, which is the closest thing to having no rules.
+    if disallow.is_empty() {
+        output.push_str("Disallow:\n");
+    }
+
+    for path in disallow {
+        output.push_str(&format!("Disallow: {path}\n"));
+    }
+}
+
+// Every URL path generated for a collection.
+fn collection_paths(feed: &Feed) -> impl Iterator<Item = String> + '_ {
+    [feed.index_url.path(), feed.feed_url.path()]
+        .into_iter()
+        .chain(feed.entries.iter().map(|entry| entry.url.path()))
+        .map(ToOwned::to_owned)
+}
+
+// Generate a robots.txt following the Gemini robots.txt companion specification.
+pub fn generate_robots(config: &RobotsConfig, feeds: &[Feed]) -> String {
+    let mut output = String::new();
+
+    for rule in &config.rules {
+        let mut disallow = rule.disallow.iter().cloned().collect::<BTreeSet<_>>();
+
+        for feed in feeds {
+            if rule.collections.contains(&feed.name) {
+                disallow.extend(collection_paths(feed));
+            }
+
+            // Drafts aren't published, but this prevents their URLs from being crawled the moment
+            // they are.
+            if rule.drafts {
+                disallow.extend(feed.drafts.iter().map(|entry| entry.url.path().to_owned()));
+            }
+        }
+
+        let user_agents = rule
+            .user_agents
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+
+        push_group(&mut output, &user_agents, &disallow);
+    }
+
+    let noindex = feeds
+        .iter()
+        .flat_map(|feed| &feed.entries)
+        .filter(|entry| entry.metadata.noindex)
+        .map(|entry| entry.url.path().to_owned())
+        .collect::<BTreeSet<_>>();
+
+    if !noindex.is_empty() {
+        push_group(&mut output, &[NOINDEX_USER_AGENT], &noindex);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::*;
+    use crate::config::RobotsRuleConfig;
+    use crate::feed::tests::{entry, feed};
+
+    fn rule(user_agents: &[&str], disallow: &[&str], collections: &[&str]) -> RobotsRuleConfig {
+        RobotsRuleConfig {
+            user_agents: user_agents.iter().map(|&agent| agent.to_owned()).collect(),
+            disallow: disallow.iter().map(|&path| path.to_owned()).collect(),
+            collections: collections.iter().map(|&name| name.to_owned()).collect(),
+            drafts: false,
+        }
+    }
+
+    fn feeds() -> Vec<Feed> {
+        let mut hidden = entry("hidden", "2024-01-01T00:00:00Z", &[]);
+        hidden.metadata.noindex = true;
+
+        let mut draft = entry("draft", "2024-01-01T00:00:00Z", &[]);
+        draft.metadata.draft = true;
+
+        let mut main = feed(
+            "main",
+            vec![entry("hello", "2024-01-01T00:00:00Z", &[]), hidden],
+        );
+        main.drafts.push(draft);
+
+        let capsule_url = Url::parse("gemini://example.com/").unwrap();
+        let mut note = entry("hi", "2024-01-01T00:00:00Z", &[]);
+        note.url = capsule_url.join("notes/hi.gmi").unwrap();
+
+        let mut notes = feed("notes", vec![note]);
+        notes.index_url = capsule_url.join("notes/").unwrap();
+        notes.feed_url = capsule_url.join("notes/atom.xml").unwrap();
+
+        vec![main, notes]
+    }
+
+    #[test]
+    fn writes_rules_from_the_config() {
+        let config = RobotsConfig {
+            rules: vec![
+                rule(&["archiver", "researcher"], &["/private/", "/drafts/"], &[]),
+                rule(&["webproxy"], &[], &[]),
+            ],
+        };
+
+        assert_eq!(
+            generate_robots(&config, &[]),
+            "User-agent: archiver\nUser-agent: researcher\nDisallow: /drafts/\nDisallow: /private/\n\nUser-agent: webproxy\nDisallow:\n"
+        );
+    }
+
+    #[test]
+    fn disallows_noindex_posts_for_indexers() {
+        let config = RobotsConfig { rules: Vec::new() };
+
+        assert_eq!(
+            generate_robots(&config, &feeds()),
+            "User-agent: indexer\nDisallow: /hidden.gmi\n"
+        );
+    }
+
+    #[test]
+    fn disallows_collections_and_drafts() {
+        let mut drafts_rule = rule(&["*"], &[], &[]);
+        drafts_rule.drafts = true;
+
+        let config = RobotsConfig {
+            rules: vec![rule(&["archiver"], &["/private/"], &["notes"]), drafts_rule],
+        };
+
+        assert_eq!(
+            generate_robots(&config, &feeds()),
+            "User-agent: archiver\nDisallow: /notes/\nDisallow: /notes/atom.xml\nDisallow: /notes/hi.gmi\nDisallow: /private/\n\nUser-agent: *\nDisallow: /draft.gmi\n\nUser-agent: indexer\nDisallow: /hidden.gmi\n"
+        );
+    }
+
+    #[test]
+    fn writes_nothing_without_rules_or_noindex_posts() {
+        let config = RobotsConfig { rules: Vec::new() };
+        assert_eq!(generate_robots(&config, &[]), "");
+    }
+}
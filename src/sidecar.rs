@@ -0,0 +1,460 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use eyre::bail;
+
+use crate::error::Error;
+use crate::file_ext::METADATA_FILE_EXT;
+
+// Edit the YAML files that people write by hand, like sidecar metadata files and the gempost.yaml.
+// Deserializing and serializing them would lose their comments and formatting, so these edit the
+// text of the file directly.
+
+// Find the metadata file for the post with the given slug.
+pub fn find(posts_dir: &Path, slug: &str) -> eyre::Result<PathBuf> {
+    let path = posts_dir.join(format!("{slug}.{METADATA_FILE_EXT}"));
+
+    if !path.exists() {
+        bail!(Error::NonexistentPost {
+            slug: slug.to_owned()
+        });
+    }
+
+    Ok(path)
+}
+
+// Find where the comment at the end of a line starts, ignoring `#` characters inside of quotes.
+fn comment_start(line: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut previous = ' ';
+
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '#') if previous.is_whitespace() => return Some(index),
+            _ => {}
+        }
+
+        previous = c;
+    }
+
+    None
+}
+
+fn split_lines(source: &str) -> (Vec<String>, &'static str) {
+    let line_ending = if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    (source.lines().map(str::to_owned).collect(), line_ending)
+}
+
+fn join_lines(lines: &[String], line_ending: &str) -> String {
+    let mut output = lines.join(line_ending);
+    output.push_str(line_ending);
+    output
+}
+
+// The number of lines after the first line of a top-level key that are part of its value. Block
+// lists can start at the same indentation as their key. Comments and blank lines are part of the
+// value when there's more of the value after them.
+fn value_len(lines: &[String], index: usize) -> usize {
+    let mut len = 0;
+
+    for (offset, line) in lines[index + 1..].iter().enumerate() {
+        if line.starts_with([' ', '\t']) || line.starts_with("- ") {
+            len = offset + 1;
+        } else if !line.is_empty() && !line.starts_with('#') {
+            break;
+        }
+    }
+
+    len
+}
+
+// The value on the same line as a top-level key and the comment after it, if any.
+fn split_inline_value<'a>(line: &'a str, prefix: &str) -> (&'a str, String) {
+    let rest = &line[prefix.len()..];
+
+    match comment_start(rest) {
+        Some(start) => (rest[..start].trim(), format!(" {}", &rest[start..])),
+        None => (rest.trim(), String::new()),
+    }
+}
+
+// Set a top-level key in a YAML file to a value, which must already be formatted as YAML. If the
+// key is already set, its value is replaced, keeping any comment on the same line. Otherwise, the
+// key is added to the end of the file.
+pub fn set_value(source: &str, key: &str, value: &str) -> String {
+    let (mut lines, line_ending) = split_lines(source);
+    let prefix = format!("{key}:");
+
+    match lines.iter().position(|line| line.starts_with(&prefix)) {
+        Some(index) => {
+            let (_, comment) = split_inline_value(&lines[index], &prefix);
+            lines[index] = format!("{prefix} {value}{comment}");
+
+            // Remove the rest of the old value if it spans multiple lines.
+            let len = value_len(&lines, index);
+            lines.drain(index + 1..index + 1 + len);
+        }
+        None => lines.push(format!("{prefix} {value}")),
+    }
+
+    join_lines(&lines, line_ending)
+}
+
+// Add an item to the end of a top-level list in a YAML file, creating the list if it doesn't
+// exist. The item is a map of keys to values, which must already be formatted as YAML. This returns
+// `None` if the list is written in a form we can't add to, like a non-empty `[a, b]` list.
+pub fn append_list_item(source: &str, key: &str, item: &[(&str, String)]) -> Option<String> {
+    let (mut lines, line_ending) = split_lines(source);
+    let prefix = format!("{key}:");
+
+    let (index, indent) = match lines.iter().position(|line| line.starts_with(&prefix)) {
+        Some(index) => {
+            let (value, comment) = split_inline_value(&lines[index], &prefix);
+
+            match value {
+                "" => {}
+                "[]" => lines[index] = format!("{prefix}{comment}"),
+                _ => return None,
+            }
+
+            let len = value_len(&lines, index);
+
+            // Match the indentation of the items already in the list.
+            let indent = lines[index + 1..index + 1 + len]
+                .iter()
+                .find(|line| line.trim_start().starts_with('-'))
+                .map(|line| line[..line.len() - line.trim_start().len()].to_owned())
+                .unwrap_or_else(|| String::from("  "));
+
+            (index + len, indent)
+        }
+        None => {
+            lines.push(prefix);
+            (lines.len() - 1, String::from("  "))
+        }
+    };
+
+    let item_lines = item
+        .iter()
+        .enumerate()
+        .map(|(field_index, (field, value))| {
+            let marker = if field_index == 0 { "- " } else { "  " };
+            format!("{indent}{marker}{field}: {value}")
+        });
+
+    lines.splice(index + 1..index + 1, item_lines);
+
+    Some(join_lines(&lines, line_ending))
+}
+
+// The key of a line in a map, if it's a `key: value` line.
+fn map_entry_key(line: &str) -> Option<String> {
+    let entry: BTreeMap<String, serde_yaml::Value> = serde_yaml::from_str(line.trim()).ok()?;
+    entry.into_keys().next()
+}
+
+// Find the lines of a top-level map in a YAML file, making sure it's written with one entry per
+// line. This returns the index of the line with the key and the number of lines after it, or `None`
+// if the map is written in a form we can't edit, like a non-empty `{a: b}` map.
+fn find_map(lines: &mut Vec<String>, key: &str) -> Option<(usize, usize)> {
+    let prefix = format!("{key}:");
+
+    match lines.iter().position(|line| line.starts_with(&prefix)) {
+        Some(index) => {
+            let (value, comment) = split_inline_value(&lines[index], &prefix);
+
+            match value {
+                "" => {}
+                "{}" => lines[index] = format!("{prefix}{comment}"),
+                _ => return None,
+            }
+
+            Some((index, value_len(lines, index)))
+        }
+        None => {
+            lines.push(prefix);
+            Some((lines.len() - 1, 0))
+        }
+    }
+}
+
+// Set an entry in a top-level map in a YAML file, creating the map if it doesn't exist. The value
+// must already be formatted as YAML. This returns `None` if the map is written in a form we can't
+// edit, like a non-empty `{a: b}` map.
+pub fn set_map_entry(source: &str, key: &str, entry_key: &str, value: &str) -> Option<String> {
+    let (mut lines, line_ending) = split_lines(source);
+    let (index, len) = find_map(&mut lines, key)?;
+    let entry_lines = index + 1..index + 1 + len;
+
+    // Match the indentation of the entries already in the map.
+    let indent = lines[entry_lines.clone()]
+        .iter()
+        .find(|line| !line.trim_start().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|line| line[..line.len() - line.trim_start().len()].to_owned())
+        .unwrap_or_else(|| String::from("  "));
+
+    let entry = format!("{indent}{}: {value}", quote(entry_key));
+
+    match entry_lines
+        .clone()
+        .find(|&line_index| map_entry_key(&lines[line_index]).as_deref() == Some(entry_key))
+    {
+        Some(line_index) => {
+            let comment = comment_start(&lines[line_index])
+                .map(|start| format!(" {}", &lines[line_index][start..]))
+                .unwrap_or_default();
+
+            lines[line_index] = format!("{entry}{comment}");
+        }
+        None => lines.insert(entry_lines.end, entry),
+    }
+
+    Some(join_lines(&lines, line_ending))
+}
+
+// Remove an entry from a top-level map in a YAML file, if it's there.
+pub fn remove_map_entry(source: &str, key: &str, entry_key: &str) -> Option<String> {
+    let (mut lines, line_ending) = split_lines(source);
+
+    if !lines
+        .iter()
+        .any(|line| line.starts_with(&format!("{key}:")))
+    {
+        return Some(source.to_owned());
+    }
+
+    let (index, len) = find_map(&mut lines, key)?;
+
+    let remaining = lines
+        .drain(index + 1..index + 1 + len)
+        .filter(|line| map_entry_key(line).as_deref() != Some(entry_key))
+        .collect::<Vec<_>>();
+
+    if remaining.len() == len {
+        return Some(source.to_owned());
+    }
+
+    // A key with nothing after it is null rather than an empty map.
+    if !remaining.iter().any(|line| map_entry_key(line).is_some()) {
+        let (_, comment) = split_inline_value(&lines[index], &format!("{key}:"));
+        lines[index] = format!("{key}: {{}}{comment}");
+    }
+
+    lines.splice(index + 1..index + 1, remaining);
+
+    Some(join_lines(&lines, line_ending))
+}
+
+// Format a string as a double-quoted YAML string.
+pub fn quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_existing_values() {
+        let source = "# Metadata\ntitle: \"A # B\" # The title\ndraft: true\n";
+
+        assert_eq!(
+            set_value(source, "title", "\"C\""),
+            "# Metadata\ntitle: \"C\" # The title\ndraft: true\n"
+        );
+        assert_eq!(
+            set_value(source, "draft", "false"),
+            "# Metadata\ntitle: \"A # B\" # The title\ndraft: false\n"
+        );
+    }
+
+    #[test]
+    fn adds_missing_values() {
+        assert_eq!(
+            set_value("title: Title\ndraft_notes: x\n", "draft", "false"),
+            "title: Title\ndraft_notes: x\ndraft: false\n"
+        );
+        assert_eq!(
+            set_value("title: Title", "draft", "false"),
+            "title: Title\ndraft: false\n"
+        );
+    }
+
+    #[test]
+    fn replaces_values_spanning_multiple_lines() {
+        let source = "summary: >\n  Folded\n  text\ncategories:\n- one\n- two\ndraft: true\n";
+
+        assert_eq!(
+            set_value(source, "summary", "\"New\""),
+            "summary: \"New\"\ncategories:\n- one\n- two\ndraft: true\n"
+        );
+        assert_eq!(
+            set_value(source, "categories", "[]"),
+            "summary: >\n  Folded\n  text\ncategories: []\ndraft: true\n"
+        );
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        assert_eq!(
+            set_value("title: Title\r\ndraft: true\r\n", "draft", "false"),
+            "title: Title\r\ndraft: false\r\n"
+        );
+    }
+
+    #[test]
+    fn quotes_strings() {
+        assert_eq!(quote("a \"b\" \\ c\nd"), "\"a \\\"b\\\" \\\\ c\\nd\"");
+
+        let value: String = serde_yaml::from_str(&quote("a \"b\" \\ c\nd # e")).unwrap();
+        assert_eq!(value, "a \"b\" \\ c\nd # e");
+    }
+
+    fn change() -> [(&'static str, String); 2] {
+        [
+            ("updated", String::from("\"2024-01-01T00:00:00Z\"")),
+            ("note", String::from("\"Fixed a typo\"")),
+        ]
+    }
+
+    #[test]
+    fn creates_missing_lists() {
+        assert_eq!(
+            append_list_item("title: Title\n", "changelog", &change()).as_deref(),
+            Some("title: Title\nchangelog:\n  - updated: \"2024-01-01T00:00:00Z\"\n    note: \"Fixed a typo\"\n")
+        );
+        assert_eq!(
+            append_list_item("changelog: [] # History\ntitle: Title\n", "changelog", &change())
+                .as_deref(),
+            Some("changelog: # History\n  - updated: \"2024-01-01T00:00:00Z\"\n    note: \"Fixed a typo\"\ntitle: Title\n")
+        );
+    }
+
+    #[test]
+    fn appends_to_existing_lists() {
+        let source = "changelog:\n# The first change\n- updated: \"2023-01-01T00:00:00Z\"\n  note: >\n    Folded\n    note\ntitle: Title\n";
+
+        assert_eq!(
+            append_list_item(source, "changelog", &change()).as_deref(),
+            Some("changelog:\n# The first change\n- updated: \"2023-01-01T00:00:00Z\"\n  note: >\n    Folded\n    note\n- updated: \"2024-01-01T00:00:00Z\"\n  note: \"Fixed a typo\"\ntitle: Title\n")
+        );
+    }
+
+    #[test]
+    fn leaves_comments_after_lists_alone() {
+        let source = "changelog:\n  - updated: \"2023-01-01T00:00:00Z\"\n\n    note: \"First\"\n\n# Other fields\ntitle: Title\n";
+
+        assert_eq!(
+            append_list_item(source, "changelog", &change()).as_deref(),
+            Some("changelog:\n  - updated: \"2023-01-01T00:00:00Z\"\n\n    note: \"First\"\n  - updated: \"2024-01-01T00:00:00Z\"\n    note: \"Fixed a typo\"\n\n# Other fields\ntitle: Title\n")
+        );
+    }
+
+    #[test]
+    fn appends_to_lists_with_crlf_line_endings() {
+        let source =
+            "changelog:\r\n    - updated: \"2023-01-01T00:00:00Z\"\r\n      note: \"First\"\r\n";
+
+        assert_eq!(
+            append_list_item(source, "changelog", &change()).as_deref(),
+            Some("changelog:\r\n    - updated: \"2023-01-01T00:00:00Z\"\r\n      note: \"First\"\r\n    - updated: \"2024-01-01T00:00:00Z\"\r\n      note: \"Fixed a typo\"\r\n")
+        );
+    }
+
+    #[test]
+    fn refuses_to_append_to_flow_lists() {
+        let source = "changelog: [{updated: \"2023-01-01T00:00:00Z\", note: First}]\n";
+        assert_eq!(append_list_item(source, "changelog", &change()), None);
+    }
+
+    #[test]
+    fn sets_map_entries() {
+        let source = "redirects:\n    # Old posts\n    /a.gmi: /b.gmi # Moved\n    \"/c.gmi\": \"/d.gmi\"\ntitle: Title\n";
+
+        assert_eq!(
+            set_map_entry(source, "redirects", "/a.gmi", "\"/e.gmi\"").as_deref(),
+            Some("redirects:\n    # Old posts\n    \"/a.gmi\": \"/e.gmi\" # Moved\n    \"/c.gmi\": \"/d.gmi\"\ntitle: Title\n")
+        );
+        assert_eq!(
+            set_map_entry(source, "redirects", "/f.gmi", "\"/g.gmi\"").as_deref(),
+            Some("redirects:\n    # Old posts\n    /a.gmi: /b.gmi # Moved\n    \"/c.gmi\": \"/d.gmi\"\n    \"/f.gmi\": \"/g.gmi\"\ntitle: Title\n")
+        );
+    }
+
+    #[test]
+    fn creates_missing_maps() {
+        assert_eq!(
+            set_map_entry("title: Title\n", "redirects", "/a.gmi", "\"/b.gmi\"").as_deref(),
+            Some("title: Title\nredirects:\n  \"/a.gmi\": \"/b.gmi\"\n")
+        );
+        assert_eq!(
+            set_map_entry(
+                "redirects: {} # None yet\r\n",
+                "redirects",
+                "/a.gmi",
+                "\"/b.gmi\""
+            )
+            .as_deref(),
+            Some("redirects: # None yet\r\n  \"/a.gmi\": \"/b.gmi\"\r\n")
+        );
+    }
+
+    #[test]
+    fn removes_map_entries() {
+        let source =
+            "redirects:\n  /a.gmi: /b.gmi\n  # Comment\n  \"/c.gmi\": \"/d.gmi\"\ntitle: Title\n";
+
+        assert_eq!(
+            remove_map_entry(source, "redirects", "/c.gmi").as_deref(),
+            Some("redirects:\n  /a.gmi: /b.gmi\n  # Comment\ntitle: Title\n")
+        );
+        assert_eq!(
+            remove_map_entry(source, "redirects", "/e.gmi").as_deref(),
+            Some(source)
+        );
+        assert_eq!(
+            remove_map_entry("title: Title\n", "redirects", "/a.gmi").as_deref(),
+            Some("title: Title\n")
+        );
+    }
+
+    #[test]
+    fn leaves_an_empty_map_after_removing_the_last_entry() {
+        assert_eq!(
+            remove_map_entry(
+                "redirects: # Moved posts\r\n  /a.gmi: /b.gmi\r\n",
+                "redirects",
+                "/a.gmi"
+            )
+            .as_deref(),
+            Some("redirects: {} # Moved posts\r\n")
+        );
+        assert_eq!(
+            remove_map_entry("redirects: {} # None yet\n", "redirects", "/a.gmi").as_deref(),
+            Some("redirects: {} # None yet\n")
+        );
+    }
+
+    #[test]
+    fn refuses_to_edit_flow_maps() {
+        let source = "redirects: {/a.gmi: /b.gmi}\n";
+
+        assert_eq!(
+            set_map_entry(source, "redirects", "/c.gmi", "\"/d.gmi\""),
+            None
+        );
+        assert_eq!(remove_map_entry(source, "redirects", "/a.gmi"), None);
+    }
+}
@@ -1,14 +1,161 @@
+use std::collections::BTreeMap;
 use std::fs::{self, File};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, Datelike, FixedOffset};
 use eyre::{bail, eyre, WrapErr};
 use serde::{Deserialize, Serialize};
 use tera::{Context, Tera};
 
-use crate::entry::{AuthorMetadata, Entry};
+use crate::config::Config;
+use crate::entry::{AuthorMetadata, ChangelogEntry, Entry, EntryLink, EntryNav, EntryTranslation};
 use crate::error::Error;
 use crate::feed::{Feed, FeedAuthor};
+use crate::filters::register_filters;
+
+// The names that the index page, post page, and author page templates are loaded under. Each
+// collection has its own index page and post page templates.
+const INDEX_TEMPLATE_NAME: &str = "index";
+const POST_TEMPLATE_NAME: &str = "post";
+const AUTHOR_TEMPLATE_NAME: &str = "author";
+
+// The extension of the templates in the templates directory and the pages directory.
+pub const TEMPLATE_EXT: &str = ".tera";
+
+fn index_template_name(collection: &str) -> String {
+    format!("{INDEX_TEMPLATE_NAME}:{collection}")
+}
+
+fn post_template_name(collection: &str) -> String {
+    format!("{POST_TEMPLATE_NAME}:{collection}")
+}
+
+// The feed for every collection in the capsule, keyed by collection name.
+pub type CollectionsTemplateData = BTreeMap<String, FeedTemplateData>;
+
+// Recursively find all the files in a directory, along with their path relative to `base_dir`
+// using forward slashes.
+pub fn find_files(
+    base_dir: &Path,
+    dir: &Path,
+    files: &mut Vec<(PathBuf, String)>,
+) -> eyre::Result<()> {
+    let mut paths = fs::read_dir(dir)
+        .wrap_err("failed reading directory contents")?
+        .map(|dir_entry_result| dir_entry_result.map(|dir_entry| dir_entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .wrap_err("failed reading directory entry")?;
+
+    // Sort the paths so the order doesn't depend on the order we read them from the filesystem.
+    paths.sort();
+
+    for path in paths {
+        // Skip hidden files, like editor swap files.
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+
+        if path.is_dir() {
+            find_files(base_dir, &path, files)?;
+        } else if path.is_file() {
+            let relative_path = path
+                .strip_prefix(base_dir)?
+                .iter()
+                .map(|segment| segment.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            files.push((path, relative_path));
+        }
+    }
+
+    Ok(())
+}
+
+// Load all the templates in the templates directory, as well as the index page, post page, and
+// author page templates, which may live outside of it.
+pub fn load_templates(
+    config: &Config,
+    collections: &CollectionsTemplateData,
+    warn_handler: impl Fn(&str),
+) -> eyre::Result<Tera> {
+    let mut tera = Tera::default();
+
+    register_filters(&mut tera, collections).wrap_err("failed registering template filters")?;
+
+    // The templates directory is optional, since the index page and post page templates can live
+    // anywhere.
+    if config.templates_dir.is_dir() {
+        let mut files = Vec::new();
+        find_files(&config.templates_dir, &config.templates_dir, &mut files)
+            .wrap_err("failed finding templates")?;
+
+        // Each template is named by its path relative to the templates directory, so that
+        // templates can refer to each other in `extends`, `include`, and `import` tags. Other files,
+        // like a README or images, are skipped.
+        let named_files = files
+            .into_iter()
+            .filter(|(path, relative_path)| {
+                let is_template = relative_path.ends_with(TEMPLATE_EXT);
+
+                if !is_template {
+                    warn_handler(&format!(
+                        "This file in the templates directory is not a .tera file: {}",
+                        path.to_string_lossy()
+                    ));
+                }
+
+                is_template
+            })
+            .map(|(path, relative_path)| (path, Some(relative_path)))
+            .collect::<Vec<_>>();
+
+        if let Err(err) = tera.add_template_files(named_files) {
+            bail!(Error::InvalidTemplatesDir {
+                path: config.templates_dir.clone(),
+                reason: err.to_string(),
+            });
+        }
+    }
+
+    for collection in config.all_collections() {
+        if let Err(err) = tera.add_template_file(
+            &collection.index_template_file,
+            Some(&index_template_name(&collection.name)),
+        ) {
+            bail!(Error::InvalidIndexPageTemplate {
+                reason: err.to_string()
+            });
+        }
+
+        if let Err(err) = tera.add_template_file(
+            &collection.post_template_file,
+            Some(&post_template_name(&collection.name)),
+        ) {
+            bail!(Error::InvalidPostPageTemplate {
+                path: collection.post_template_file.clone(),
+                reason: err.to_string(),
+            });
+        }
+    }
+
+    // The author page template only needs to exist if we're generating author pages.
+    if config.author_index_path.is_some() {
+        if let Err(err) =
+            tera.add_template_file(&config.author_template_file, Some(AUTHOR_TEMPLATE_NAME))
+        {
+            bail!(Error::InvalidAuthorPageTemplate {
+                path: config.author_template_file.clone(),
+                reason: err.to_string(),
+            });
+        }
+    }
+
+    Ok(tera)
+}
 
 #[derive(Debug, PartialEq, Eq, Serialize)]
 pub struct EntryAuthorTemplateData {
@@ -27,19 +174,92 @@ impl From<AuthorMetadata> for EntryAuthorTemplateData {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct EntryLinkTemplateData {
+    pub title: String,
+    pub url: String,
+    pub date: String,
+}
+
+impl From<EntryLink> for EntryLinkTemplateData {
+    fn from(value: EntryLink) -> Self {
+        Self {
+            title: value.title,
+            url: value.url.to_string(),
+            date: value.date.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct EntryNavTemplateData {
+    pub previous: Option<EntryLinkTemplateData>,
+    pub next: Option<EntryLinkTemplateData>,
+}
+
+impl From<EntryNav> for EntryNavTemplateData {
+    fn from(value: EntryNav) -> Self {
+        Self {
+            previous: value.previous.map(Into::into),
+            next: value.next.map(Into::into),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct EntryTranslationTemplateData {
+    pub lang: Option<String>,
+    pub title: String,
+    pub url: String,
+}
+
+impl From<EntryTranslation> for EntryTranslationTemplateData {
+    fn from(value: EntryTranslation) -> Self {
+        Self {
+            lang: value.lang,
+            title: value.title,
+            url: value.url.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct EntryChangeTemplateData {
+    pub updated: String,
+    pub note: String,
+}
+
+impl From<ChangelogEntry> for EntryChangeTemplateData {
+    fn from(value: ChangelogEntry) -> Self {
+        Self {
+            updated: value.updated.to_rfc3339(),
+            note: value.note,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Serialize)]
 pub struct EntryTemplateData {
     pub id: String,
     pub url: String,
     pub title: String,
     pub body: String,
+    pub excerpt: Option<String>,
     pub updated: String,
     pub summary: Option<String>,
     pub published: Option<String>,
     pub author: Option<EntryAuthorTemplateData>,
+    pub contributors: Vec<EntryAuthorTemplateData>,
     pub rights: Option<String>,
     pub lang: Option<String>,
     pub categories: Vec<String>,
+    pub previous: Option<EntryLinkTemplateData>,
+    pub next: Option<EntryLinkTemplateData>,
+    pub category_nav: BTreeMap<String, EntryNavTemplateData>,
+    pub related: Vec<EntryLinkTemplateData>,
+    pub translations: Vec<EntryTranslationTemplateData>,
+    pub changelog: Vec<EntryChangeTemplateData>,
+    pub extra: BTreeMap<String, serde_yaml::Value>,
 }
 
 impl From<Entry> for EntryTemplateData {
@@ -49,6 +269,7 @@ impl From<Entry> for EntryTemplateData {
             url: params.url.to_string(),
             title: params.metadata.title,
             body: params.body,
+            excerpt: params.excerpt,
             updated: params.metadata.updated.to_rfc3339(),
             summary: params.metadata.summary,
             published: params
@@ -57,9 +278,31 @@ impl From<Entry> for EntryTemplateData {
                 .as_ref()
                 .map(DateTime::<FixedOffset>::to_rfc3339),
             author: params.metadata.author.map(Into::into),
+            contributors: params
+                .metadata
+                .contributors
+                .into_iter()
+                .map(Into::into)
+                .collect(),
             rights: params.metadata.rights,
             lang: params.metadata.lang,
             categories: params.metadata.categories,
+            previous: params.nav.previous.map(Into::into),
+            next: params.nav.next.map(Into::into),
+            category_nav: params
+                .category_nav
+                .into_iter()
+                .map(|(category, nav)| (category, nav.into()))
+                .collect(),
+            related: params.related.into_iter().map(Into::into).collect(),
+            translations: params.translations.into_iter().map(Into::into).collect(),
+            changelog: params
+                .metadata
+                .changelog
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            extra: params.metadata.extra,
         }
     }
 }
@@ -67,26 +310,19 @@ impl From<Entry> for EntryTemplateData {
 impl EntryTemplateData {
     pub fn render(
         &self,
+        tera: &Tera,
         feed: &FeedTemplateData,
-        template: &Path,
+        collections: &CollectionsTemplateData,
         output: &Path,
     ) -> eyre::Result<()> {
-        let mut tera = Tera::default();
-
-        if let Err(err) = tera.add_template_file(template, Some("post")) {
-            bail!(Error::InvalidPostPageTemplate {
-                path: output.to_owned(),
-                reason: err.to_string(),
-            });
-        }
-
         let mut context = Context::new();
         context.insert("entry", self);
         context.insert("feed", feed);
+        context.insert("collections", collections);
 
         let dest_file = File::create(output).wrap_err("failed creating gemlog post page file")?;
 
-        if let Err(err) = tera.render_to("post", &context, dest_file) {
+        if let Err(err) = tera.render_to(&post_template_name(&feed.name), &context, dest_file) {
             bail!(Error::InvalidPostPageTemplate {
                 path: output.to_owned(),
                 reason: err.to_string(),
@@ -98,28 +334,95 @@ impl EntryTemplateData {
 }
 
 impl FeedTemplateData {
-    pub fn render_index(&self, template: &Path, output: &Path) -> eyre::Result<()> {
-        let mut tera = Tera::default();
+    pub fn render_index(
+        &self,
+        tera: &Tera,
+        collections: &CollectionsTemplateData,
+        output: &Path,
+    ) -> eyre::Result<()> {
+        let mut context = Context::new();
+        context.insert("feed", self);
+        context.insert("collections", collections);
+
+        let parent_dir = output.parent().ok_or(eyre!(
+            "Could not get parent directory of index page file. This is a bug."
+        ))?;
 
-        if let Err(err) = tera.add_template_file(template, Some("index")) {
+        fs::create_dir_all(parent_dir).wrap_err("failed creating parent directory")?;
+
+        let dest_file = File::create(output).wrap_err("failed creating gemlog index page file")?;
+
+        if let Err(err) = tera.render_to(&index_template_name(&self.name), &context, dest_file) {
             bail!(Error::InvalidIndexPageTemplate {
-                reason: err.to_string()
+                reason: err.to_string(),
             });
         }
 
+        Ok(())
+    }
+
+    pub fn render_author_index(
+        &self,
+        tera: &Tera,
+        author: &FeedAuthorTemplateData,
+        collections: &CollectionsTemplateData,
+        output: &Path,
+    ) -> eyre::Result<()> {
         let mut context = Context::new();
         context.insert("feed", self);
+        context.insert("author", author);
+        context.insert("collections", collections);
 
         let parent_dir = output.parent().ok_or(eyre!(
-            "Could not get parent directory of index page file. This is a bug."
+            "Could not get parent directory of author page file. This is a bug."
         ))?;
 
         fs::create_dir_all(parent_dir).wrap_err("failed creating parent directory")?;
 
-        let dest_file = File::create(output).wrap_err("failed creating gemlog index page file")?;
+        let dest_file = File::create(output).wrap_err("failed creating author page file")?;
 
-        if let Err(err) = tera.render_to("index", &context, dest_file) {
-            bail!(Error::InvalidIndexPageTemplate {
+        if let Err(err) = tera.render_to(AUTHOR_TEMPLATE_NAME, &context, dest_file) {
+            bail!(Error::InvalidAuthorPageTemplate {
+                path: output.to_owned(),
+                reason: err.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn render_page(
+        &self,
+        tera: &mut Tera,
+        template: &Path,
+        collections: &CollectionsTemplateData,
+        output: &Path,
+    ) -> eyre::Result<()> {
+        // Name the page by its path so it can't collide with templates in the templates directory.
+        let template_name = format!("page:{}", template.to_string_lossy());
+
+        if let Err(err) = tera.add_template_file(template, Some(&template_name)) {
+            bail!(Error::InvalidPageTemplate {
+                path: template.to_owned(),
+                reason: err.to_string(),
+            });
+        }
+
+        let mut context = Context::new();
+        context.insert("feed", self);
+        context.insert("collections", collections);
+
+        let parent_dir = output.parent().ok_or(eyre!(
+            "Could not get parent directory of page file. This is a bug."
+        ))?;
+
+        fs::create_dir_all(parent_dir).wrap_err("failed creating parent directory")?;
+
+        let dest_file = File::create(output).wrap_err("failed creating page file")?;
+
+        if let Err(err) = tera.render_to(&template_name, &context, dest_file) {
+            bail!(Error::InvalidPageTemplate {
+                path: template.to_owned(),
                 reason: err.to_string(),
             });
         }
@@ -190,28 +493,42 @@ impl From<PostPathParams> for PostPathTemplateData {
 
 impl PostPathTemplateData {
     pub fn render(&self, template: &str) -> eyre::Result<String> {
-        let mut tera = Tera::default();
+        let values = [
+            ("year", self.year.as_str()),
+            ("month", self.month.as_str()),
+            ("day", self.day.as_str()),
+            ("slug", self.slug.as_str()),
+        ];
+
+        render_path(template, &values, |reason| Error::InvalidPostPath {
+            template: template.to_owned(),
+            reason,
+        })
+    }
+}
 
-        if let Err(err) = tera.add_raw_template("path", template) {
-            bail!(Error::InvalidPostPath {
-                template: template.to_owned(),
-                reason: err.to_string(),
-            });
-        }
+// Render one of the path templates from the config file, with each of `values` available as a
+// variable. If the template is invalid, `invalid_path` makes the error from the reason why.
+pub fn render_path(
+    template: &str,
+    values: &[(&str, &str)],
+    invalid_path: impl Fn(String) -> Error,
+) -> eyre::Result<String> {
+    let mut tera = Tera::default();
+
+    if let Err(err) = tera.add_raw_template("path", template) {
+        bail!(invalid_path(err.to_string()));
+    }
 
-        let mut context = Context::new();
-        context.insert("year", &self.year);
-        context.insert("month", &self.month);
-        context.insert("day", &self.day);
-        context.insert("slug", &self.slug);
-
-        match tera.render("path", &context) {
-            Ok(path) => Ok(path),
-            Err(err) => bail!(Error::InvalidPostPath {
-                template: template.to_owned(),
-                reason: err.to_string(),
-            }),
-        }
+    let mut context = Context::new();
+
+    for (name, value) in values {
+        context.insert(*name, value);
+    }
+
+    match tera.render("path", &context) {
+        Ok(path) => Ok(path),
+        Err(err) => bail!(invalid_path(err.to_string())),
     }
 }
 
@@ -234,6 +551,8 @@ impl From<FeedAuthor> for FeedAuthorTemplateData {
 
 #[derive(Debug, PartialEq, Eq, Serialize)]
 pub struct FeedTemplateData {
+    pub id: String,
+    pub name: String,
     pub capsule_url: String,
     pub feed_url: String,
     pub index_url: String,
@@ -242,12 +561,16 @@ pub struct FeedTemplateData {
     pub subtitle: Option<String>,
     pub rights: Option<String>,
     pub author: Option<FeedAuthorTemplateData>,
+    pub lang: Option<String>,
+    pub extra: BTreeMap<String, serde_yaml::Value>,
     pub entries: Vec<EntryTemplateData>,
 }
 
 impl From<Feed> for FeedTemplateData {
     fn from(feed: Feed) -> Self {
         Self {
+            id: feed.id.to_string(),
+            name: feed.name,
             capsule_url: feed.capsule_url.to_string(),
             feed_url: feed.feed_url.to_string(),
             index_url: feed.index_url.to_string(),
@@ -256,6 +579,8 @@ impl From<Feed> for FeedTemplateData {
             subtitle: feed.subtitle,
             rights: feed.rights,
             author: feed.author.map(Into::into),
+            lang: feed.lang,
+            extra: feed.extra,
             entries: feed.entries.into_iter().map(Into::into).collect(),
         }
     }
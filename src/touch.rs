@@ -0,0 +1,46 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::SecondsFormat;
+use eyre::{bail, WrapErr};
+
+use crate::clock;
+use crate::entry::RawEntryMetadata;
+use crate::error::Error;
+use crate::sidecar;
+
+// Set the update time of a post to now, optionally adding a note about what changed to its
+// changelog.
+pub fn touch_post(posts_dir: &Path, slug: &str, note: Option<&str>) -> eyre::Result<()> {
+    let path = sidecar::find(posts_dir, slug)?;
+
+    // Make sure the metadata file is valid before we edit it.
+    RawEntryMetadata::read(&path).wrap_err(format!(
+        "failed reading metadata file: {}",
+        path.to_string_lossy()
+    ))?;
+
+    let source = fs::read_to_string(&path).wrap_err("failed reading post metadata file")?;
+
+    let timestamp = sidecar::quote(&clock::now()?.to_rfc3339_opts(SecondsFormat::Secs, false));
+
+    let mut source = sidecar::set_value(&source, "updated", &timestamp);
+
+    if let Some(note) = note {
+        let change = [("updated", timestamp), ("note", sidecar::quote(note))];
+
+        source = match sidecar::append_list_item(&source, "changelog", &change) {
+            Some(source) => source,
+            None => bail!(Error::InvalidMetadataFile {
+                path: path.clone(),
+                reason: String::from(
+                    "The `changelog` must be written as a list with one item per line so gempost can add to it."
+                ),
+            }),
+        };
+    }
+
+    fs::write(&path, source).wrap_err("failed writing post metadata file")?;
+
+    Ok(())
+}